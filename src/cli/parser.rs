@@ -1,4 +1,6 @@
-use clap::{Parser, Subcommand};
+use std::str::FromStr;
+
+use clap::{ArgAction, Args, Parser, Subcommand, ValueEnum};
 
 #[derive(Parser, Debug, Clone)]
 #[command(
@@ -8,23 +10,222 @@ use clap::{Parser, Subcommand};
     long_about = None
 )]
 pub struct CLIArguments {
-    /// The file/path to mutate.
-    pub file: String,
-    /// Required for the `Add` command. Ignored if using the `Remove` command.
+    /// The file/path to mutate. Not used by the `Apply`, `Undo` and `History` commands.
+    pub file: Option<String>,
+    #[clap(long = "file", value_name = "PATH")]
+    /// Another file to mutate the same way. Can be repeated, and the files are mutated in parallel.
+    pub files: Vec<String>,
+    #[clap(short = 'j', long, value_name = "N", value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..))]
+    /// How many files to mutate at the same time. Defaults to the number of CPUs.
+    pub jobs: Option<usize>,
+    /// Required for the `Add` command. Not allowed for the other commands.
     pub entry: Option<String>,
+    #[clap(flatten)]
+    pub entry_options: EntryOptions,
+    #[clap(flatten)]
+    pub write_options: WriteOptions,
+    #[clap(subcommand)]
+    pub command: What,
+}
+
+#[derive(Args, Debug, Clone, Default)]
+pub struct EntryOptions {
+    #[clap(long, value_name = "PATH", conflicts_with = "entry")]
+    /// Read the entry from a file instead of the command line, e.g. for entries spanning several lines.
+    pub entry_file: Option<String>,
+    #[clap(long)]
+    /// Substitute `${VAR}` and `${VAR:-default}` in the entry with environment variables.
+    pub expand_env: bool,
+    #[clap(long = "var", value_name = "KEY=VALUE", requires = "expand_env", value_parser = parse_var)]
+    /// Set a variable for `--expand-env`, taking precedence over the environment. Can be repeated.
+    pub vars: Vec<(String, String)>,
+}
+
+fn parse_var(var: &str) -> Result<(String, String), String> {
+    var.split_once('=')
+        .map(|(key, value)| (key.to_owned(), value.to_owned()))
+        .ok_or_else(|| format!("expected KEY=VALUE but found `{}`", var))
+}
+
+#[derive(Args, Debug, Clone, Default)]
+pub struct WriteOptions {
     #[clap(long)]
     /// This will not make any changes but will report the changes made if the command is executed.
     pub dry_run: bool,
-    #[clap(subcommand)]
-    pub command: What,
+    #[clap(long, conflicts_with = "dry_run")]
+    /// This will not make any changes, but exits with code 1 and reports the changes if the file would change.
+    pub check: bool,
+    #[clap(short = 'i', long, conflicts_with_all = ["dry_run", "check"])]
+    /// Ask before applying each change, showing it between the lines around it.
+    pub interactive: bool,
+    #[clap(long, value_name = "PATH", conflicts_with_all = ["dry_run", "check", "interactive"])]
+    /// Write the changes to this file as a unified diff instead of making them, e.g. for `mute apply`.
+    pub emit_patch: Option<String>,
+    #[clap(skip)]
+    /// The plan `mute plan` records the changes in instead of making them.
+    pub plan: Option<PlanFile>,
+    #[clap(long, value_name = "FORMAT", conflicts_with = "interactive", value_parser = parse_report)]
+    /// Report every file as a test case, in `junit=PATH` XML or `tap` on the standard output.
+    pub report: Option<Report>,
+    #[clap(long, value_name = "HASH")]
+    /// Only change the file if its contents have this hash, e.g. `sha256:3a7bd3e2...`. Dry-runs and checks report the hashes.
+    pub if_hash: Option<String>,
+    #[clap(long = "no-journal", action = ArgAction::SetFalse)]
+    /// Don't record the change in the journal that `undo` reverts changes from.
+    pub journal: bool,
+    #[clap(long = "lock", overrides_with = "lock")]
+    /// Hold an exclusive lock on the file while mutating it. This is the default when writing, so it only cancels an earlier `--no-lock`, e.g. one from an alias.
+    pub take_lock: bool,
+    #[clap(long = "no-lock", action = ArgAction::SetFalse, overrides_with = "take_lock")]
+    /// Don't hold an exclusive lock on the file while mutating it.
+    pub lock: bool,
+    #[clap(long, value_name = "SECONDS", default_value_t = 10)]
+    /// How long to wait for another process to release its lock on the file before giving up.
+    pub lock_timeout: u64,
+    #[clap(long, overrides_with = "replace_symlink")]
+    /// Mutate the file a symlink points to. This is the default, so it only cancels an earlier `--replace-symlink`, e.g. one from an alias.
+    pub follow_symlinks: bool,
+    #[clap(long, overrides_with = "follow_symlinks")]
+    /// Replace a symlink with a regular file holding the mutated contents, leaving the file it pointed to alone.
+    pub replace_symlink: bool,
+    #[clap(long)]
+    /// Keep the modification time the file had before it was mutated.
+    pub preserve_mtime: bool,
+    #[clap(long)]
+    /// Treat a missing file as empty and create it.
+    pub create: bool,
+    #[clap(long, requires = "create")]
+    /// Also create the missing parent directories of a file created by `--create`.
+    pub parents: bool,
+    #[clap(long, requires = "create", value_parser = parse_mode)]
+    /// The permissions, in octal, of a file created by `--create`, e.g. `600`.
+    pub mode: Option<u32>,
+    #[clap(long)]
+    /// Refuse to mutate a file that has uncommitted changes in its git repository.
+    pub git_clean: bool,
+    #[clap(long)]
+    /// Skip the files git doesn't track, e.g. untracked or ignored ones.
+    pub git_tracked_only: bool,
+    #[clap(long)]
+    /// Stage the mutated file in its git repository.
+    pub git_add: bool,
+    #[clap(flatten)]
+    pub display: DisplayOptions,
+}
+
+#[derive(Args, Debug, Clone, Default)]
+pub struct DisplayOptions {
+    #[clap(short = 'C', long, value_name = "N")]
+    /// Show N unchanged lines before and after each change reported by a dry-run or a check.
+    pub context: Option<usize>,
+    #[clap(short = 'A', long, value_name = "N")]
+    /// Show N unchanged lines after each change, overriding `--context`.
+    pub after_context: Option<usize>,
+    #[clap(short = 'B', long, value_name = "N")]
+    /// Show N unchanged lines before each change, overriding `--context`.
+    pub before_context: Option<usize>,
+    #[clap(long, value_enum, value_name = "WHEN", default_value_t = ColorWhen::Auto)]
+    /// Color added and removed lines and highlight what patterns matched.
+    pub color: ColorWhen,
+    #[clap(long, value_enum, value_name = "STYLE", default_value_t = DiffStyle::Line)]
+    /// How to lay out the changes reported by a dry-run or a check.
+    pub diff_style: DiffStyle,
+}
+
+impl DisplayOptions {
+    /// How many unchanged lines to show before and after each change.
+    pub fn context_lines(&self) -> (usize, usize) {
+        (
+            self.before_context.or(self.context).unwrap_or(0),
+            self.after_context.or(self.context).unwrap_or(0),
+        )
+    }
+}
+
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ColorWhen {
+    /// Only when printing to a terminal and `NO_COLOR` isn't set.
+    #[default]
+    Auto,
+    Always,
+    Never,
+}
+
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DiffStyle {
+    /// Removed and added lines one after the other.
+    #[default]
+    Line,
+    /// A changed line as a single line, with the words that changed marked.
+    Word,
+    /// The original lines next to the mutated ones, in two columns as wide as the terminal.
+    SideBySide,
+}
+
+/// Where `mute plan` writes its plan, and the command it planned.
+#[derive(Debug, Clone)]
+pub struct PlanFile {
+    pub path: String,
+    pub arguments: Vec<String>,
+}
+
+/// A test report of the files mutated, for CI to show each of them as a test case.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Report {
+    /// A JUnit XML file.
+    Junit(String),
+    /// The Test Anything Protocol, printed instead of the usual output.
+    Tap,
+}
+
+fn parse_report(report: &str) -> Result<Report, String> {
+    match report.split_once('=') {
+        Some(("junit", path)) if !path.is_empty() => Ok(Report::Junit(path.to_owned())),
+        None if report == "tap" => Ok(Report::Tap),
+        _ => Err(format!(
+            "expected `junit=PATH` or `tap` but found `{}`",
+            report
+        )),
+    }
+}
+
+fn parse_mode(mode: &str) -> Result<u32, String> {
+    u32::from_str_radix(mode, 8)
+        .ok()
+        .filter(|&mode| mode <= 0o7777)
+        .ok_or_else(|| format!("`{}` is not an octal file mode", mode))
 }
 
 #[derive(Debug, Subcommand, Clone)]
 pub enum What {
     /// This mode will add an additional line or overwrite a line in an existing file at a position specified by a regex or a line number.
-    Add(Where),
+    Add(AddWhere),
     /// This mode will remove a line from a file at a position specified by a regex or a line number.
     Remove(Where),
+    /// This mode will comment out the lines at a position specified by a regex or a line number.
+    Comment(CommentWhere),
+    /// This mode will uncomment the lines at a position specified by a regex or a line number.
+    Uncomment(CommentWhere),
+    /// This mode will move lines specified by a line number, a range or a regex to a new position.
+    Move(Relocation),
+    /// This mode will copy lines specified by a line number, a range or a regex to a new position.
+    Copy(Relocation),
+    /// This mode will swap two blocks of lines specified by line numbers, ranges or regexes.
+    Swap(Swap),
+    /// This mode will sort the lines in a range or between two regexes.
+    Sort(Sort),
+    /// This mode will remove repeated lines in a range or between two regexes.
+    Dedupe(Dedupe),
+    /// This mode will apply every add and remove operation listed in a recipe file, scanning and writing the file only once.
+    Recipe(Recipe),
+    /// This mode will record the changes the command after it would make, and the hash of every file, in a plan for `mute apply` instead of making them.
+    Plan(Plan),
+    /// This mode will apply a patch written by `--emit-patch`, or a plan written by `mute plan`, to the files it names. No file is needed.
+    Apply(Apply),
+    /// This mode will revert the last changes recorded in the journal. No file is needed.
+    Undo(Undo),
+    /// This mode will list the changes recorded in the journal, most recent first. No file is needed.
+    History,
 }
 
 #[derive(Parser, Debug, Clone)]
@@ -33,26 +234,415 @@ pub struct Where {
     pub command: WhereCommands,
 }
 
+#[derive(Parser, Debug, Clone)]
+pub struct AddWhere {
+    #[clap(subcommand)]
+    pub command: AddCommands,
+}
+
+#[derive(Parser, Debug, Clone)]
+pub struct CommentWhere {
+    #[clap(long)]
+    /// The comment marker to use instead of inferring it from the file extension, e.g. `//` or `'<!-- -->'`.
+    pub marker: Option<String>,
+    #[clap(subcommand)]
+    pub command: WhereCommands,
+}
+
 #[derive(Subcommand, Debug, Clone)]
 pub enum WhereCommands {
     /// The line after a regex match is effected. Note: multiple matches can happen in a file.
     AfterPattern(PatternMutation),
-    /// The line at the specified line number (starting from 1) is effected.
+    /// The line at the specified line number (starting from 1, or from -1 for the last line) is effected.
     AtLine(LineMutation),
     /// The line before a regex match is effected. Note: multiple matches can happen in a file.
     BeforePattern(PatternMutation),
     /// The line which matches a regex is replaced. Note: multiple matches can happen in a file.
     OverwritePattern(PatternMutation),
-    /// The line specified by the line number (starting from 1) is over written with the entry.
+    /// The line specified by the line number (starting from 1, or from -1 for the last line) is over written with the entry.
     OverwriteLine(LineMutation),
 }
 
+impl WhereCommands {
+    /// The pattern of a pattern subcommand.
+    pub fn pattern(&self) -> Option<&PatternMutation> {
+        match self {
+            WhereCommands::AfterPattern(pattern_mut)
+            | WhereCommands::BeforePattern(pattern_mut)
+            | WhereCommands::OverwritePattern(pattern_mut) => Some(pattern_mut),
+            _ => None,
+        }
+    }
+}
+
+/// Where the `Add` command adds its entry: the places every command can address, and the ends of
+/// the file, which only make sense for adding.
+#[derive(Subcommand, Debug, Clone)]
+pub enum AddCommands {
+    #[command(flatten)]
+    Where(WhereCommands),
+    /// The entry is added as a new line at the end of the file.
+    Append,
+    /// The entry is added as a new line at the start of the file.
+    Prepend,
+}
+
 #[derive(Parser, Debug, Clone)]
 pub struct PatternMutation {
     pub pattern: String,
+    #[clap(long, value_name = "MARKER", num_args = 0..=1, require_equals = true)]
+    /// Comment lines are never matched. The comment marker is inferred from the file extension unless given, e.g. `--ignore-comments='//'`.
+    pub ignore_comments: Option<Option<String>>,
+    #[clap(flatten)]
+    pub options: MatchOptions,
+    #[clap(flatten)]
+    pub expected: ExpectedMatches,
+}
+
+/// How many times a pattern must match for the file to be changed at all.
+#[derive(Args, Debug, Clone, Copy, Default)]
+pub struct ExpectedMatches {
+    #[clap(long, value_name = "N", conflicts_with_all = ["expect_at_least", "expect_at_most"])]
+    /// Abort without changing the file unless the pattern matches exactly N times.
+    pub expect_count: Option<usize>,
+    #[clap(long, value_name = "N")]
+    /// Abort without changing the file if the pattern matches fewer than N times.
+    pub expect_at_least: Option<usize>,
+    #[clap(long, value_name = "N")]
+    /// Abort without changing the file if the pattern matches more than N times.
+    pub expect_at_most: Option<usize>,
+}
+
+impl ExpectedMatches {
+    /// Whether the pattern is asserted not to match at all, with `--expect-count 0`, so finding
+    /// nothing to change is a success rather than a pattern that wasn't found.
+    pub fn allows_no_match(&self) -> bool {
+        self.expect_count == Some(0)
+    }
+}
+
+#[derive(Args, Debug, Clone, Default)]
+pub struct MatchOptions {
+    #[clap(short = 'F', long)]
+    /// Treat the pattern as a literal string instead of a regex.
+    pub fixed_strings: bool,
+    #[clap(short = 'i', long)]
+    /// Match regardless of upper/lower case.
+    pub ignore_case: bool,
+    #[clap(short = 'w', long)]
+    /// Only match the pattern where no word character comes right before or after it, like `grep -w`.
+    pub word: bool,
+    #[clap(short = 'x', long)]
+    /// Only match the pattern against the whole line.
+    pub line: bool,
+    #[clap(short = 'v', long)]
+    /// Select the lines which do not match the pattern instead.
+    pub invert_match: bool,
+    #[clap(long)]
+    /// Match the pattern against the whole file instead of line by line, so a match can span several lines.
+    pub multiline: bool,
 }
 
 #[derive(Parser, Debug, Clone)]
 pub struct LineMutation {
-    pub line_number: usize,
+    /// Negative line numbers count backwards from the end of the file.
+    #[clap(allow_negative_numbers = true)]
+    pub line_number: isize,
+}
+
+/// An inclusive range of line numbers written as `START:END`, e.g. `3:5` or `-3:-1`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LineRange {
+    pub start: isize,
+    pub end: isize,
+}
+
+impl FromStr for LineRange {
+    type Err = String;
+
+    fn from_str(range: &str) -> Result<Self, Self::Err> {
+        let (start, end) = range
+            .split_once(':')
+            .ok_or_else(|| format!("expected START:END but found `{}`", range))?;
+        let parse = |line_no: &str| {
+            line_no
+                .trim()
+                .parse::<isize>()
+                .map_err(|_| format!("`{}` is not a line number", line_no))
+        };
+        Ok(LineRange {
+            start: parse(start)?,
+            end: parse(end)?,
+        })
+    }
+}
+
+#[derive(Args, Debug, Clone, Default)]
+#[group(required = true, multiple = false)]
+pub struct SourceAddress {
+    #[clap(long, allow_negative_numbers = true)]
+    /// The line at this line number.
+    pub from_line: Option<isize>,
+    #[clap(long, value_name = "START:END", allow_hyphen_values = true)]
+    /// The lines from START to END, both included.
+    pub from_lines: Option<LineRange>,
+    #[clap(long)]
+    /// Every line matching this regex.
+    pub from_pattern: Option<String>,
+}
+
+#[derive(Args, Debug, Clone, Default)]
+#[group(required = true, multiple = false)]
+pub struct DestinationAddress {
+    #[clap(long)]
+    /// After every line matching this regex.
+    pub after_pattern: Option<String>,
+    #[clap(long)]
+    /// Before every line matching this regex.
+    pub before_pattern: Option<String>,
+    #[clap(long, allow_negative_numbers = true)]
+    /// At this line number, pushing the line that was there down.
+    pub at_line: Option<isize>,
+}
+
+#[derive(Parser, Debug, Clone)]
+pub struct Relocation {
+    #[clap(flatten)]
+    pub source: SourceAddress,
+    #[clap(flatten)]
+    pub destination: DestinationAddress,
+    #[clap(flatten)]
+    pub options: MatchOptions,
+}
+
+#[derive(Args, Debug, Clone, Default)]
+#[group(required = true, multiple = false)]
+pub struct SwapAddress {
+    #[clap(long, allow_negative_numbers = true)]
+    /// Swap with the line at this line number.
+    pub with_line: Option<isize>,
+    #[clap(long, value_name = "START:END", allow_hyphen_values = true)]
+    /// Swap with the lines from START to END, both included.
+    pub with_lines: Option<LineRange>,
+    #[clap(long)]
+    /// Swap with the lines matching this regex.
+    pub with_pattern: Option<String>,
+}
+
+#[derive(Parser, Debug, Clone)]
+pub struct Swap {
+    #[clap(flatten)]
+    pub first: SourceAddress,
+    #[clap(flatten)]
+    pub second: SwapAddress,
+    #[clap(flatten)]
+    pub options: MatchOptions,
+}
+
+impl From<&SwapAddress> for SourceAddress {
+    fn from(address: &SwapAddress) -> Self {
+        SourceAddress {
+            from_line: address.with_line,
+            from_lines: address.with_lines,
+            from_pattern: address.with_pattern.clone(),
+        }
+    }
+}
+
+#[derive(Args, Debug, Clone, Default)]
+#[group(required = true, multiple = false)]
+pub struct Region {
+    #[clap(long, value_name = "START:END", allow_hyphen_values = true)]
+    /// The lines from START to END, both included.
+    pub lines: Option<LineRange>,
+    #[clap(long, num_args = 2, value_names = ["START", "END"])]
+    /// The lines after each line matching the START regex, up to the next line matching the END regex (or the end of the file).
+    pub between: Option<Vec<String>>,
+}
+
+#[derive(Parser, Debug, Clone)]
+pub struct Sort {
+    #[clap(flatten)]
+    pub region: Region,
+    #[clap(flatten)]
+    pub order: SortOrder,
+}
+
+#[derive(Args, Debug, Clone, Default)]
+pub struct SortOrder {
+    #[clap(short = 'n', long, conflicts_with = "natural")]
+    /// Order by the number the lines (or keys) start with.
+    pub numeric: bool,
+    #[clap(long)]
+    /// Order numbers inside of the lines (or keys) by their value, so `item2` comes before `item10`.
+    pub natural: bool,
+    #[clap(short = 'i', long)]
+    /// Order regardless of upper/lower case.
+    pub ignore_case: bool,
+    #[clap(short = 'r', long)]
+    /// Sort in descending order.
+    pub reverse: bool,
+    #[clap(long, value_name = "REGEX")]
+    /// Order by the part of the line this regex matches, or its first capture group if it has one.
+    pub key: Option<String>,
+}
+
+#[derive(Parser, Debug, Clone)]
+pub struct Dedupe {
+    #[clap(flatten)]
+    pub region: Region,
+    #[clap(long, value_enum, default_value_t = Keep::First)]
+    /// Which of the repeated lines is kept.
+    pub keep: Keep,
+    #[clap(short = 'i', long)]
+    /// Lines differing only in upper/lower case are repeats.
+    pub ignore_case: bool,
+    #[clap(long, value_name = "REGEX")]
+    /// Lines are repeats when this regex matches the same text in them, or the same first capture group if it has one.
+    pub key: Option<String>,
+}
+
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Keep {
+    #[default]
+    First,
+    Last,
+}
+
+#[derive(Parser, Debug, Clone)]
+pub struct Plan {
+    #[clap(short = 'o', long, value_name = "PATH")]
+    /// Where to write the plan. Can also come last, after the command.
+    pub output: Option<String>,
+    #[clap(
+        trailing_var_arg = true,
+        allow_hyphen_values = true,
+        required = true,
+        value_name = "COMMAND"
+    )]
+    /// The command to plan, with its options, files and entry, e.g. `app.toml 'x = 1' add append`.
+    pub command: Vec<String>,
+}
+
+impl Plan {
+    /// Where to write the plan and the command to plan, with `-o PATH` taken off the end of the
+    /// command when it was given there.
+    pub fn output_and_command(&self) -> Option<(String, Vec<String>)> {
+        if let Some(output) = &self.output {
+            return Some((output.clone(), self.command.clone()));
+        }
+        let (last, command) = self.command.split_last()?;
+        if let Some(output) = last.strip_prefix("--output=") {
+            return Some((output.to_owned(), command.to_vec()));
+        }
+        let (flag, command) = command.split_last()?;
+        (flag == "-o" || flag == "--output").then(|| (last.clone(), command.to_vec()))
+    }
+}
+
+#[derive(Parser, Debug, Clone)]
+pub struct Apply {
+    /// The unified diff or the plan to apply.
+    pub patch: String,
+    #[clap(short = 'R', long)]
+    /// Undo the patch or plan instead, turning the files it was applied to back into what they were.
+    pub reverse: bool,
+}
+
+#[derive(Parser, Debug, Clone)]
+pub struct Undo {
+    #[clap(default_value_t = 1)]
+    /// How many of the most recent changes to revert.
+    pub count: usize,
+    #[clap(long)]
+    /// Revert even when a file has changed since mute changed it.
+    pub force: bool,
+}
+
+#[derive(Parser, Debug, Clone)]
+pub struct Recipe {
+    /// A file listing one operation per line, written like the arguments that follow the file to mutate, e.g. `"entry=1" add after-pattern '^\[table]'`.
+    pub recipe: String,
+}
+
+/// A line of a recipe file.
+#[derive(Parser, Debug, Clone)]
+#[command(no_binary_name = true)]
+pub struct RecipeStep {
+    /// Required for the `Add` command. Not allowed for the `Remove` command.
+    pub entry: Option<String>,
+    #[clap(subcommand)]
+    pub command: RecipeCommand,
+}
+
+#[derive(Subcommand, Debug, Clone)]
+pub enum RecipeCommand {
+    /// Adds or overwrites a line, like the `Add` command.
+    Add(AddWhere),
+    /// Removes a line, like the `Remove` command.
+    Remove(Where),
+}
+
+#[cfg(test)]
+mod tests {
+    use clap::{CommandFactory, Parser};
+
+    use crate::cli::parser::{CLIArguments, RecipeStep, What};
+
+    #[test]
+    fn test_cli_definition() {
+        CLIArguments::command().debug_assert();
+        RecipeStep::command().debug_assert();
+    }
+
+    #[test]
+    fn test_plan_output() {
+        let output_and_command = |arguments: &[&str]| {
+            let cli_args = CLIArguments::parse_from(["mute", "plan"].iter().chain(arguments));
+            let What::Plan(plan) = cli_args.command else {
+                panic!("expected the Plan command")
+            };
+            plan.output_and_command()
+        };
+        let command = vec!["a.toml".to_owned(), "add".to_owned(), "append".to_owned()];
+        // before the command or after it
+        for arguments in [
+            &["-o", "p.json", "a.toml", "add", "append"][..],
+            &["a.toml", "add", "append", "-o", "p.json"],
+            &["a.toml", "add", "append", "--output=p.json"],
+        ] {
+            assert_eq!(
+                Some(("p.json".to_owned(), command.clone())),
+                output_and_command(arguments)
+            );
+        }
+        assert_eq!(None, output_and_command(&["a.toml", "add", "append"]));
+    }
+
+    #[test]
+    fn test_append_only_adds() {
+        let parses = |command: &str| {
+            CLIArguments::try_parse_from(["mute", "a.toml", command, "append"]).is_ok()
+        };
+        assert!(parses("add"));
+        assert!(!parses("remove"));
+        assert!(!parses("comment"));
+        assert!(!parses("uncomment"));
+    }
+
+    #[test]
+    fn test_lock_flags() {
+        let lock = |arguments: &[&str]| {
+            let mut command_line = vec!["mute"];
+            command_line.extend_from_slice(arguments);
+            command_line.extend_from_slice(&["a.toml", "add", "append"]);
+            CLIArguments::parse_from(command_line).write_options.lock
+        };
+        // locking is the default, and the last of --lock and --no-lock wins
+        assert!(lock(&[]));
+        assert!(lock(&["--lock"]));
+        assert!(!lock(&["--no-lock"]));
+        assert!(lock(&["--no-lock", "--lock"]));
+        assert!(!lock(&["--lock", "--no-lock"]));
+    }
 }
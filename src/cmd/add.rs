@@ -1,210 +1,255 @@
-use regex::Regex;
+use sha2::{Digest, Sha256};
 use std::{
-    fs::{self},
-    io::Write, process,
+    fs,
+    io::{Read, Seek, SeekFrom},
 };
 
-use super::NEW_LINE;
-use crate::cmd::file_from_dry_run;
-
-pub fn add_after_pattern(file_path: String, pattern: String, entry: String, dry_run: bool) {
-    let contents = fs::read_to_string(&file_path).unwrap();
-    let mut this_line = false;
-    let mut lines_found = 0;
-    let mut file = file_from_dry_run(file_path, dry_run);
-
-    let regex = Regex::new(pattern.as_str()).unwrap();
-
-    let mut line_iter = contents.split('\n').enumerate().peekable();
-    while let Some((index, line)) = line_iter.next() {
-        if regex.is_match(line) {
-            lines_found += 1;
-            this_line = true;
-        }
-        if !dry_run {
-            file.write_all(line.as_bytes()).unwrap();
+use super::{
+    change::Change,
+    check_changes, check_hash, commit_changes, file_exists, find_line, git, hash_hex,
+    interactive::confirm_changes,
+    is_writing, line_count,
+    matcher::{LineMatcher, LineSpan},
+    pattern_not_found, read_file, report_changes, take_snapshot, write_contents, write_file,
+    Written, NEW_LINE,
+};
+use crate::cli::parser::WriteOptions;
 
-            if this_line {
-                file.write_all(&[NEW_LINE]).unwrap();
-                file.write_all(entry.as_bytes()).unwrap();
-                this_line = false;
-            }
+pub fn add_after_pattern(
+    file_path: String,
+    matcher: &LineMatcher,
+    entry: String,
+    options: &WriteOptions,
+) {
+    let (contents, snapshot) = read_file(&file_path, options);
+    let changes: Vec<Change> = matcher
+        .find_spans(&contents)
+        .into_iter()
+        .map(|span| {
+            Change::insert(span.end + 1, &entry)
+                .matching(span)
+                .matched_by(matcher)
+        })
+        .collect();
 
-            if line_iter.peek().is_some() {
-                file.write_all(&[NEW_LINE]).unwrap();
-            }
-        } else if this_line {
-            println!("(Line: {})\t --- {}", index + 1 + lines_found, entry);
-            this_line = false;
-        }
-    }
-    file.flush().unwrap();
-    if lines_found == 0 {
-        println!("WARNING: Pattern was not found. Please check the file, the regex and try again.");
-        process::exit(exitcode::DATAERR);
+    if changes.is_empty() && !matcher.allows_no_match() {
+        pattern_not_found();
     }
+    commit_changes(file_path, &contents, snapshot, &changes, options);
 }
 
-pub fn add_via_line_number(file_path: String, line_no: usize, entry: String, dry_run: bool) {
-    assert_ne!(
-        line_no, 0,
-        "Line numbers are 1-indexed. Cannot have a line 0."
+pub fn add_via_line_number(
+    file_path: String,
+    line_no: isize,
+    entry: String,
+    options: &WriteOptions,
+) {
+    let (contents, snapshot) = read_file(&file_path, options);
+    let index = find_line(&contents, line_no);
+    commit_changes(
+        file_path,
+        &contents,
+        snapshot,
+        &[Change::insert(index, &entry)],
+        options,
     );
+}
 
-    let contents = fs::read_to_string(&file_path).unwrap();
-    let mut line_found = false;
-    let mut file = file_from_dry_run(file_path, dry_run);
+pub fn add_before_pattern(
+    file_path: String,
+    matcher: &LineMatcher,
+    entry: String,
+    options: &WriteOptions,
+) {
+    let (contents, snapshot) = read_file(&file_path, options);
+    let changes: Vec<Change> = matcher
+        .find_spans(&contents)
+        .into_iter()
+        .map(|span| {
+            Change::insert(span.start, &entry)
+                .matching(span)
+                .matched_by(matcher)
+        })
+        .collect();
 
-    let mut line_iter = contents.split('\n').enumerate().peekable();
-    while let Some((index, line)) = line_iter.next() {
-        if index + 1 == line_no {
-            line_found = true;
-            if dry_run {
-                println!("(Line: {})\t --- {}", line_no, line);
-                println!("(Line: {})\t +++ {}", line_no, entry);
-                println!("(Line: {})\t +++ {}", line_no + 1, line);
-            } else {
-                file.write_all(entry.as_bytes()).unwrap();
-                file.write_all(&[NEW_LINE]).unwrap();
-            }
-        }
-        if !dry_run {
-            file.write_all(line.as_bytes()).unwrap();
-            if line_iter.peek().is_some() {
-                file.write_all(&[NEW_LINE]).unwrap();
-            }
-        }
+    if changes.is_empty() && !matcher.allows_no_match() {
+        pattern_not_found();
     }
+    commit_changes(file_path, &contents, snapshot, &changes, options);
+}
 
-    file.flush().unwrap();
-    if !line_found {
-        println!("WARNING: Line was not found. Please check the file and try again.");
-        process::exit(exitcode::DATAERR);
-    }
+pub fn overwrite_via_line_number(
+    file_path: String,
+    line_no: isize,
+    entry: String,
+    options: &WriteOptions,
+) {
+    let (contents, snapshot) = read_file(&file_path, options);
+    let index = find_line(&contents, line_no);
+    let lines: Vec<&str> = contents.split('\n').collect();
+    let span = LineSpan {
+        start: index,
+        end: index,
+    };
+    commit_changes(
+        file_path,
+        &contents,
+        snapshot,
+        &[Change::replace(&lines, span, &entry)],
+        options,
+    );
 }
 
-pub fn add_before_pattern(file_path: String, pattern: String, entry: String, dry_run: bool) {
-    let contents = fs::read_to_string(&file_path).unwrap();
-    let mut this_line = false;
-    let mut lines_found = 0;
-    let mut file = file_from_dry_run(file_path, dry_run);
+pub fn overwrite_pattern(
+    file_path: String,
+    matcher: &LineMatcher,
+    entry: String,
+    options: &WriteOptions,
+) {
+    let (contents, snapshot) = read_file(&file_path, options);
+    let lines: Vec<&str> = contents.split('\n').collect();
+    let changes: Vec<Change> = matcher
+        .find_spans(&contents)
+        .into_iter()
+        .map(|span| Change::replace(&lines, span, &entry).matched_by(matcher))
+        .collect();
 
-    let regex = Regex::new(pattern.as_str()).unwrap();
+    if changes.is_empty() && !matcher.allows_no_match() {
+        pattern_not_found();
+    }
+    commit_changes(file_path, &contents, snapshot, &changes, options);
+}
 
-    let mut line_iter = contents.split('\n').enumerate().peekable();
-    while let Some((index, line)) = line_iter.next() {
-        if regex.is_match(line) {
-            lines_found += 1;
-            this_line = true;
+pub fn append_entry(file_path: String, entry: String, options: &WriteOptions) {
+    // a missing file is empty and an interactive run shows the file, so there's nothing to gain
+    // from streaming through them
+    if !is_writing(options) || options.interactive || !file_exists(&file_path, options) {
+        let (contents, snapshot) = read_file(&file_path, options);
+        let mutated = contents.clone() + &appended(contents.as_bytes().last().copied(), &entry);
+        let changes = [Change::insert(line_count(&contents), &entry)];
+        if options.interactive && confirm_changes(&contents, &changes, &options.display).is_empty()
+        {
+            return;
         }
-        if !dry_run {
-            if this_line {
-                file.write_all(entry.as_bytes()).unwrap();
-                file.write_all(&[NEW_LINE]).unwrap();
-                this_line = false;
-            }
-            file.write_all(line.as_bytes()).unwrap();
-            if line_iter.peek().is_some() {
-                file.write_all(&[NEW_LINE]).unwrap();
-            }
-        } else if this_line {
-            println!("(Line: {})\t --- {}", index + lines_found, entry);
-            this_line = false;
+        if options.check {
+            check_changes(&contents, &mutated, &changes, &options.display);
         }
+        if !is_writing(options) {
+            report_changes(&file_path, &contents, &mutated, &changes, options);
+            return;
+        }
+        write_contents(file_path, &contents, snapshot, &mutated, &changes, options);
+        return;
     }
-    file.flush().unwrap();
-    if lines_found == 0 {
-        println!("WARNING: Pattern was not found. Please check the file, the regex and try again.");
-        process::exit(exitcode::DATAERR);
-    }
-}
-
-pub fn overwrite_via_line_number(file_path: String, line_no: usize, entry: String, dry_run: bool) {
-    assert_ne!(
-        line_no, 0,
-        "Line numbers are 1-indexed. Cannot have a line 0."
-    );
-
-    let contents = fs::read_to_string(&file_path).unwrap();
-    let mut line_found = false;
-    let mut file = file_from_dry_run(file_path, dry_run);
 
-    let mut line_iter = contents.split('\n').enumerate().peekable();
-    while let Some((index, line)) = line_iter.next() {
-        if index + 1 == line_no {
-            line_found = true;
-            if dry_run {
-                println!("(Line: {})\t --- {}", line_no, line);
-                println!("(Line: {})\t +++ {}", line_no, entry);
-            } else {
-                file.write_all(entry.as_bytes()).unwrap();
-                if line_iter.peek().is_some() {
-                    file.write_all(&[NEW_LINE]).unwrap();
-                }
-            }
-        } else if !dry_run {
-            file.write_all(line.as_bytes()).unwrap();
-            if line_iter.peek().is_some() {
-                file.write_all(&[NEW_LINE]).unwrap();
+    // The file is never read into memory so appending to large files (logs) stays cheap. Only the
+    // journal and `--if-hash` need to stream through the whole file, everything else just looks
+    // at the last byte.
+    if options.git_clean {
+        git::ensure_clean(&file_path);
+    }
+    let snapshot = take_snapshot(&file_path, options);
+    let mut file = fs::File::open(&file_path).unwrap();
+    let mut hasher = Sha256::new();
+    let mut new_lines = 0;
+    if options.journal || options.if_hash.is_some() {
+        let mut buffer = vec![0u8; 64 * 1024];
+        loop {
+            let read = file.read(&mut buffer).unwrap();
+            if read == 0 {
+                break;
             }
+            hasher.update(&buffer[..read]);
+            new_lines += buffer[..read]
+                .iter()
+                .filter(|&&byte| byte == NEW_LINE)
+                .count();
         }
+        check_hash(
+            &file_path,
+            &hash_hex(hasher.clone().finalize().as_slice()),
+            options,
+        );
     }
+    let last_byte = if snapshot.len == 0 {
+        None
+    } else {
+        let mut last_byte = [0u8; 1];
+        file.seek(SeekFrom::End(-1)).unwrap();
+        file.read_exact(&mut last_byte).unwrap();
+        Some(last_byte[0])
+    };
 
-    file.flush().unwrap();
-    if !line_found {
-        println!("WARNING: Line was not found. Please check the file and try again.");
-        process::exit(exitcode::DATAERR);
-    }
+    let appended = appended(last_byte, &entry);
+    let line = match last_byte {
+        None => 0,
+        Some(NEW_LINE) => new_lines,
+        Some(_) => new_lines + 1,
+    };
+    let hashes = || {
+        let before = hash_hex(hasher.clone().finalize().as_slice());
+        hasher.update(appended.as_bytes());
+        (before, hash_hex(hasher.finalize().as_slice()))
+    };
+    write_file(
+        &file_path,
+        snapshot,
+        Written::Appended(&appended),
+        hashes,
+        &[Change::insert(line, &entry)],
+        options,
+    );
 }
 
-pub fn overwrite_pattern(file_path: String, pattern: String, entry: String, dry_run: bool) {
-    let contents = fs::read_to_string(&file_path).unwrap();
-    let mut this_line = false;
-    let mut found_line = false;
-    let mut file = file_from_dry_run(file_path, dry_run);
-
-    let regex = Regex::new(pattern.as_str()).unwrap();
+/// What appending the entry to a file ending in `last_byte` writes, keeping a trailing new line
+/// at the end of the file if there was one.
+fn appended(last_byte: Option<u8>, entry: &str) -> String {
+    match last_byte {
+        None => entry.to_owned(),
+        Some(NEW_LINE) => format!("{}\n", entry),
+        Some(_) => format!("\n{}", entry),
+    }
+}
 
-    let mut line_iter = contents.split('\n').enumerate().peekable();
-    while let Some((index, line)) = line_iter.next() {
-        if regex.is_match(line) {
-            found_line = true;
-            this_line = true;
-        }
-        if !dry_run {
-            if this_line {
-                file.write_all(entry.as_bytes()).unwrap();
-                if line_iter.peek().is_some() {
-                    file.write_all(&[NEW_LINE]).unwrap();
-                }
-                this_line = false;
-            } else {
-                file.write_all(line.as_bytes()).unwrap();
-                if line_iter.peek().is_some() {
-                    file.write_all(&[NEW_LINE]).unwrap();
-                }
-            }
-        } else if this_line {
-            println!("(Line: {})\t --- {}", index + 1, line);
-            println!("(Line: {})\t +++ {}", index + 1, entry);
-            this_line = false;
-        }
+pub fn prepend_entry(file_path: String, entry: String, options: &WriteOptions) {
+    let (contents, snapshot) = read_file(&file_path, options);
+    let mutated = if contents.is_empty() {
+        entry.clone()
+    } else {
+        format!("{}\n{}", entry, contents)
+    };
+    let changes = [Change::insert(0, &entry)];
+    if options.interactive && confirm_changes(&contents, &changes, &options.display).is_empty() {
+        return;
+    }
+    if options.check {
+        check_changes(&contents, &mutated, &changes, &options.display);
     }
-    file.flush().unwrap();
-    if !found_line {
-        println!("WARNING: Pattern was not found. Please check the file, the regex and try again.");
-        process::exit(exitcode::DATAERR);
+    if !is_writing(options) {
+        report_changes(&file_path, &contents, &mutated, &changes, options);
+        return;
     }
+
+    write_contents(file_path, &contents, snapshot, &mutated, &changes, options);
 }
 
 #[cfg(test)]
 mod tests {
-    use std::io::{Read, Write};
+    use std::{
+        fs,
+        io::{Read, Write},
+    };
     use tempfile::NamedTempFile;
 
+    use crate::cli::parser::{ExpectedMatches, MatchOptions, PatternMutation, WriteOptions};
+    use crate::cmd::journal::{isolated_journal, undo};
+    use crate::cmd::matcher::LineMatcher;
+    use crate::cmd::output::capture;
+    use crate::cmd::{content_hash, FILE_CHANGED};
+
     use crate::cmd::add::{
-        add_after_pattern, add_before_pattern, add_via_line_number, overwrite_pattern,
-        overwrite_via_line_number,
+        add_after_pattern, add_before_pattern, add_via_line_number, append_entry,
+        overwrite_pattern, overwrite_via_line_number, prepend_entry,
     };
 
     const FAUX_FILE: &str = "[table]\n\
@@ -225,9 +270,9 @@ mod tests {
         // mutate file
         add_after_pattern(
             file_path,
-            "^\\[\\[subtable2]]".to_owned(),
+            &LineMatcher::new("^\\[\\[subtable2]]", &MatchOptions::default()),
             "check=1".to_owned(),
-            false,
+            &WriteOptions::default(),
         );
         // read in file
         let mut mutated_contents = String::new();
@@ -253,7 +298,12 @@ mod tests {
         file1.flush().unwrap();
         let file_path = file1.path().to_str().unwrap().to_owned();
         // mutate file
-        add_via_line_number(file_path, 3, "entry_0=\"zero\"".to_owned(), false);
+        add_via_line_number(
+            file_path,
+            3,
+            "entry_0=\"zero\"".to_owned(),
+            &WriteOptions::default(),
+        );
         // read in file
         let mut mutated_contents = String::new();
         let mut file2 = file1.reopen().unwrap();
@@ -280,9 +330,9 @@ mod tests {
         // mutate file
         add_before_pattern(
             file_path,
-            "^entry_1".to_owned(),
+            &LineMatcher::new("^entry_1", &MatchOptions::default()),
             "entry_0=\"zero\"".to_owned(),
-            false,
+            &WriteOptions::default(),
         );
         // read in file
         let mut mutated_contents = String::new();
@@ -308,7 +358,7 @@ mod tests {
         file1.flush().unwrap();
         let file_path = file1.path().to_str().unwrap().to_owned();
         // mutate file
-        overwrite_via_line_number(file_path, 1, "[tabby]".to_owned(), false);
+        overwrite_via_line_number(file_path, 1, "[tabby]".to_owned(), &WriteOptions::default());
         // read in file
         let mut mutated_contents = String::new();
         let mut file2 = file1.reopen().unwrap();
@@ -334,9 +384,9 @@ mod tests {
         // mutate file
         overwrite_pattern(
             file_path,
-            "^\\[table".to_owned(),
+            &LineMatcher::new("^\\[table", &MatchOptions::default()),
             "[tabby]".to_owned(),
-            false,
+            &WriteOptions::default(),
         );
         // read in file
         let mut mutated_contents = String::new();
@@ -352,4 +402,201 @@ mod tests {
         entry_4=\"four\"";
         assert_eq!(expected, mutated_contents);
     }
+
+    #[test]
+    fn test_overwrite_negative_line() {
+        // create test file
+        let mut file1 = NamedTempFile::new().unwrap();
+        file1.write_all(FAUX_FILE.as_bytes()).unwrap();
+        file1.flush().unwrap();
+        let file_path = file1.path().to_str().unwrap().to_owned();
+        // mutate file
+        overwrite_via_line_number(
+            file_path,
+            -1,
+            "entry_4=\"five\"".to_owned(),
+            &WriteOptions::default(),
+        );
+        // read in file
+        let mut mutated_contents = String::new();
+        let mut file2 = file1.reopen().unwrap();
+        file2.read_to_string(&mut mutated_contents).unwrap();
+        // compare results
+        let expected = "[table]\n\
+        [[subtable1]]\n\
+        entry_1=\"one\"\n\
+        entry_2=\"two\"\n\
+        \n\
+        [[subtable2]]\n\
+        entry_4=\"five\"";
+        assert_eq!(expected, mutated_contents);
+    }
+
+    #[test]
+    fn test_append() {
+        // create test file
+        let mut file1 = NamedTempFile::new().unwrap();
+        file1.write_all(FAUX_FILE.as_bytes()).unwrap();
+        file1.flush().unwrap();
+        let file_path = file1.path().to_str().unwrap().to_owned();
+        // mutate file
+        append_entry(
+            file_path,
+            "entry_5=\"five\"".to_owned(),
+            &WriteOptions::default(),
+        );
+        // read in file
+        let mut mutated_contents = String::new();
+        let mut file2 = file1.reopen().unwrap();
+        file2.read_to_string(&mut mutated_contents).unwrap();
+        // compare results
+        let expected = "[table]\n\
+        [[subtable1]]\n\
+        entry_1=\"one\"\n\
+        entry_2=\"two\"\n\
+        \n\
+        [[subtable2]]\n\
+        entry_4=\"four\"\n\
+        entry_5=\"five\"";
+        assert_eq!(expected, mutated_contents);
+    }
+
+    #[test]
+    fn test_prepend() {
+        // create test file
+        let mut file1 = NamedTempFile::new().unwrap();
+        file1.write_all(FAUX_FILE.as_bytes()).unwrap();
+        file1.flush().unwrap();
+        let file_path = file1.path().to_str().unwrap().to_owned();
+        // mutate file
+        prepend_entry(file_path, "# header".to_owned(), &WriteOptions::default());
+        // read in file
+        let mut mutated_contents = String::new();
+        let mut file2 = file1.reopen().unwrap();
+        file2.read_to_string(&mut mutated_contents).unwrap();
+        // compare results
+        let expected = "# header\n\
+        [table]\n\
+        [[subtable1]]\n\
+        entry_1=\"one\"\n\
+        entry_2=\"two\"\n\
+        \n\
+        [[subtable2]]\n\
+        entry_4=\"four\"";
+        assert_eq!(expected, mutated_contents);
+    }
+
+    #[test]
+    fn test_overwrite_multiline_pattern() {
+        // create test file
+        let mut file1 = NamedTempFile::new().unwrap();
+        file1.write_all(FAUX_FILE.as_bytes()).unwrap();
+        file1.flush().unwrap();
+        let file_path = file1.path().to_str().unwrap().to_owned();
+        // mutate file
+        let options = MatchOptions {
+            multiline: true,
+            ..Default::default()
+        };
+        overwrite_pattern(
+            file_path,
+            &LineMatcher::new("^entry_1=\"one\"\nentry_2", &options),
+            "entry_12=\"twelve\"".to_owned(),
+            &WriteOptions::default(),
+        );
+        // read in file
+        let mut mutated_contents = String::new();
+        let mut file2 = file1.reopen().unwrap();
+        file2.read_to_string(&mut mutated_contents).unwrap();
+        // compare results
+        let expected = "[table]\n\
+        [[subtable1]]\n\
+        entry_12=\"twelve\"\n\
+        \n\
+        [[subtable2]]\n\
+        entry_4=\"four\"";
+        assert_eq!(expected, mutated_contents);
+    }
+
+    #[test]
+    fn test_append_if_hash() {
+        // create test file
+        let mut file1 = NamedTempFile::new().unwrap();
+        file1.write_all("a\nb\n".as_bytes()).unwrap();
+        file1.flush().unwrap();
+        let file_path = file1.path().to_str().unwrap().to_owned();
+        // mutate file
+        let options = WriteOptions {
+            if_hash: Some(content_hash("a\nb\n")),
+            ..Default::default()
+        };
+        append_entry(file_path.clone(), "c".to_owned(), &options);
+        // read in file
+        let mut mutated_contents = String::new();
+        let mut file2 = file1.reopen().unwrap();
+        file2.read_to_string(&mut mutated_contents).unwrap();
+        // compare results
+        assert_eq!("a\nb\nc\n", mutated_contents);
+        // a file with another hash is left alone
+        let outcome = capture(true, || {
+            append_entry(file_path.clone(), "d".to_owned(), &options)
+        });
+        assert_eq!(FILE_CHANGED, outcome.code);
+        assert_eq!("a\nb\nc\n", fs::read_to_string(&file_path).unwrap());
+    }
+
+    #[test]
+    fn test_append_journal() {
+        let _journal = isolated_journal();
+        // create test file
+        let mut file1 = NamedTempFile::new().unwrap();
+        file1.write_all("a\nb".as_bytes()).unwrap();
+        file1.flush().unwrap();
+        let file_path = file1.path().to_str().unwrap().to_owned();
+        // mutate file, streaming through it
+        let options = WriteOptions {
+            journal: true,
+            lock: true,
+            ..Default::default()
+        };
+        append_entry(file_path.clone(), "c".to_owned(), &options);
+        // compare results
+        assert_eq!("a\nb\nc", fs::read_to_string(&file_path).unwrap());
+        // undoing checks the hashes the journal has for the file before and after, so it only
+        // works when they were worked out right
+        let outcome = capture(true, || undo(1, false, &options));
+        assert_eq!(exitcode::OK, outcome.code);
+        assert_eq!("a\nb", fs::read_to_string(&file_path).unwrap());
+    }
+
+    #[test]
+    fn test_expect_no_match() {
+        // create test file
+        let mut file1 = NamedTempFile::new().unwrap();
+        file1.write_all(FAUX_FILE.as_bytes()).unwrap();
+        file1.flush().unwrap();
+        let file_path = file1.path().to_str().unwrap().to_owned();
+        // mutate file, asserting the pattern matches nothing
+        let pattern_mut = PatternMutation {
+            pattern: "^entry_3".to_owned(),
+            ignore_comments: None,
+            options: MatchOptions::default(),
+            expected: ExpectedMatches {
+                expect_count: Some(0),
+                ..Default::default()
+            },
+        };
+        overwrite_pattern(
+            file_path.clone(),
+            &LineMatcher::for_file(&pattern_mut, &file_path),
+            "entry_3=\"three\"".to_owned(),
+            &WriteOptions::default(),
+        );
+        // read in file
+        let mut mutated_contents = String::new();
+        let mut file2 = file1.reopen().unwrap();
+        file2.read_to_string(&mut mutated_contents).unwrap();
+        // compare results
+        assert_eq!(FAUX_FILE, mutated_contents);
+    }
 }
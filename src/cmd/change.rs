@@ -0,0 +1,358 @@
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use similar::{capture_diff_slices, Algorithm, DiffOp};
+use std::collections::HashMap;
+
+use super::{
+    line_count,
+    matcher::{LineMatcher, LineSpan},
+    output::outln,
+    render::Painter,
+};
+use crate::cli::parser::DisplayOptions;
+
+/// A single hunk of a mutation: the `removed` lines starting at `line` (0-indexed, in the original
+/// file) are replaced by the `added` lines. Inserts remove nothing and deletions add nothing.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Change {
+    pub line: usize,
+    pub removed: Vec<String>,
+    pub added: Vec<String>,
+    /// The lines a pattern matched to cause this change. Only used to give dry-runs context.
+    pub matched: Option<LineSpan>,
+    /// The regex that matched, for dry-runs to highlight what it matched. Never recorded.
+    #[serde(skip)]
+    pub pattern: Option<String>,
+}
+
+impl Change {
+    /// Inserts the entry before `line`. An entry spanning several lines, e.g. one from
+    /// `--entry-file`, adds each of them as a line of its own.
+    pub fn insert(line: usize, entry: &str) -> Change {
+        Change {
+            added: entry_lines(entry),
+            ..Change::insert_lines(line, &[])
+        }
+    }
+
+    pub fn insert_lines(line: usize, added: &[&str]) -> Change {
+        Change {
+            line,
+            removed: Vec::new(),
+            added: added.iter().map(|line| line.to_string()).collect(),
+            matched: None,
+            pattern: None,
+        }
+    }
+
+    pub fn remove(lines: &[&str], span: LineSpan) -> Change {
+        Change {
+            line: span.start,
+            removed: lines[span.start..=span.end]
+                .iter()
+                .map(|line| line.to_string())
+                .collect(),
+            added: Vec::new(),
+            matched: None,
+            pattern: None,
+        }
+    }
+
+    /// Replaces the lines of the span with the lines of the entry.
+    pub fn replace(lines: &[&str], span: LineSpan, entry: &str) -> Change {
+        Change {
+            added: entry_lines(entry),
+            ..Change::remove(lines, span)
+        }
+    }
+
+    pub fn matching(self, span: LineSpan) -> Change {
+        Change {
+            matched: Some(span),
+            ..self
+        }
+    }
+
+    /// Remembers the regex behind the change, unless what it matched can't be pointed out on a
+    /// single line.
+    pub fn matched_by(self, matcher: &LineMatcher) -> Change {
+        Change {
+            pattern: matcher.line_pattern().map(|pattern| pattern.to_owned()),
+            ..self
+        }
+    }
+
+    /// How many lines later everything after this change ends up.
+    fn shift(&self) -> isize {
+        self.added.len() as isize - self.removed.len() as isize
+    }
+}
+
+fn entry_lines(entry: &str) -> Vec<String> {
+    entry.split('\n').map(|line| line.to_owned()).collect()
+}
+
+/// Produces the mutated file contents. Changes must be sorted by line and must not overlap.
+/// Removing the final line of a file keeps the new line that ended the line before it, unless as
+/// many lines are added elsewhere, e.g. when the line is moved, which leaves the file ending the
+/// way it did.
+pub fn apply_changes(contents: &str, changes: &[Change]) -> String {
+    let lines: Vec<&str> = contents.split('\n').collect();
+    let mut mutated: Vec<&str> = Vec::with_capacity(lines.len());
+    let mut next_line = 0;
+    for change in changes {
+        mutated.extend_from_slice(&lines[next_line..change.line]);
+        mutated.extend(change.added.iter().map(|line| line.as_str()));
+        next_line = change.line + change.removed.len();
+    }
+    mutated.extend_from_slice(&lines[next_line..]);
+
+    let mut mutated_contents = mutated.join("\n");
+    let removed_last_line = changes
+        .last()
+        .is_some_and(|change| change.added.is_empty() && next_line == lines.len());
+    let shrunk = changes.iter().map(Change::shift).sum::<isize>() < 0;
+    if removed_last_line && shrunk && !mutated.is_empty() {
+        mutated_contents.push('\n');
+    }
+    mutated_contents
+}
+
+/// Works out changes that turn `contents` into `mutated`, for when only the result is known.
+pub fn changes_between(contents: &str, mutated: &str) -> Vec<Change> {
+    let lines: Vec<&str> = contents.split('\n').collect();
+    let mutated_lines: Vec<&str> = mutated.split('\n').collect();
+    let to_strings = |lines: &[&str]| lines.iter().map(|line| line.to_string()).collect();
+    let changes: Vec<Change> = capture_diff_slices(Algorithm::Myers, &lines, &mutated_lines)
+        .into_iter()
+        .filter(|op| !matches!(op, DiffOp::Equal { .. }))
+        .map(|op| Change {
+            line: op.old_range().start,
+            removed: to_strings(&lines[op.old_range()]),
+            added: to_strings(&mutated_lines[op.new_range()]),
+            matched: None,
+            pattern: None,
+        })
+        .collect();
+
+    // removing the last line keeps the new line before it, so a diff dropping that new line too
+    // can only be replayed by replacing everything
+    if apply_changes(contents, &changes) == mutated {
+        changes
+    } else {
+        vec![Change {
+            line: 0,
+            removed: to_strings(&lines),
+            added: to_strings(&mutated_lines),
+            matched: None,
+            pattern: None,
+        }]
+    }
+}
+
+/// Reports the changes the way `--dry-run` shows them. Removed lines are numbered as they are in
+/// the original file, added and unchanged lines as they will be in the mutated file. The unchanged
+/// lines shown are the ones a pattern matched and the context asked for with `-C`, `-A` and `-B`,
+/// with `--` between lines that aren't next to each other when there is context. `--diff-style`
+/// picks how the lines are laid out.
+pub fn print_changes(contents: &str, changes: &[Change], display: &DisplayOptions) {
+    let lines: Vec<&str> = contents.split('\n').collect();
+    let total_lines = line_count(contents);
+    let (before, after) = display.context_lines();
+    let painter = Painter::new(display);
+    let mut offset: isize = 0;
+    // the line of the original file that would come next, to spot gaps in what's shown
+    let mut next_line: Option<usize> = None;
+    let separate = |index: usize, next_line: &mut Option<usize>| {
+        if (before > 0 || after > 0) && next_line.is_some_and(|next_line| index != next_line) {
+            outln!("--");
+        }
+        *next_line = Some(index + 1);
+    };
+    // every change a command makes has the same pattern, so it's only compiled once
+    let mut highlights: HashMap<&str, Option<Regex>> = HashMap::new();
+    for pattern in changes
+        .iter()
+        .filter_map(|change| change.pattern.as_deref())
+    {
+        highlights
+            .entry(pattern)
+            .or_insert_with(|| Regex::new(pattern).ok());
+    }
+
+    for (position, change) in changes.iter().enumerate() {
+        let removed_end = change.line + change.removed.len();
+        let highlight = change
+            .pattern
+            .as_deref()
+            .and_then(|pattern| highlights[pattern].as_ref());
+        let is_matched = |index: usize| {
+            change
+                .matched
+                .is_some_and(|span| span.start <= index && index <= span.end)
+        };
+        let context_highlight = |index: usize| highlight.filter(|_| is_matched(index));
+
+        let unprinted = next_line.unwrap_or(0);
+        for index in (unprinted..change.line).filter(|&index| {
+            index + before >= change.line && index < total_lines || is_matched(index)
+        }) {
+            separate(index, &mut next_line);
+            painter.context(
+                index,
+                index as isize + offset + 1,
+                lines[index],
+                context_highlight(index),
+            );
+        }
+        separate(change.line, &mut next_line);
+        // the removed lines are what the pattern matched, unless it matched other lines
+        let removed_highlight = highlight.filter(|_| change.matched.is_none());
+        painter.change(change, change.line as isize + offset + 1, removed_highlight);
+        offset += change.shift();
+        next_line = Some(removed_end);
+
+        // lines the next change removes are shown as removed, not as context
+        let next_change = changes
+            .get(position + 1)
+            .map_or(lines.len(), |next| next.line);
+        for index in (removed_end..next_change).filter(|&index| {
+            index < removed_end + after && index < total_lines || is_matched(index)
+        }) {
+            separate(index, &mut next_line);
+            painter.context(
+                index,
+                index as isize + offset + 1,
+                lines[index],
+                context_highlight(index),
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::cli::parser::{ColorWhen, DisplayOptions};
+    use crate::cmd::change::{apply_changes, changes_between, print_changes, Change};
+    use crate::cmd::matcher::LineSpan;
+    use crate::cmd::output::capture;
+
+    /// What `print_changes` prints, without color, showing `context` lines around each change.
+    fn printed(contents: &str, changes: &[Change], context: usize) -> String {
+        let display = DisplayOptions {
+            context: Some(context),
+            color: ColorWhen::Never,
+            ..Default::default()
+        };
+        capture(true, || print_changes(contents, changes, &display)).stdout
+    }
+
+    #[test]
+    fn test_print_changes() {
+        // create test file
+        let contents = "a\nb\nc\nd\ne\nf\ng\nh\n";
+        let lines: Vec<&str> = contents.split('\n').collect();
+        let span = |start, end| LineSpan { start, end };
+
+        // changes far apart are shown with their context and a separator between them
+        let changes = [
+            Change::replace(&lines, span(1, 1), "B"),
+            Change::replace(&lines, span(6, 6), "G"),
+        ];
+        let expected = "(Line: 1)\t     a\n(Line: 2)\t --- b\n(Line: 2)\t +++ B\n(Line: 3)\t     c\n--\n\
+                        (Line: 6)\t     f\n(Line: 7)\t --- g\n(Line: 7)\t +++ G\n(Line: 8)\t     h\n";
+        assert_eq!(expected, printed(contents, &changes, 1));
+
+        // changes whose context touches share it, without a separator
+        let changes = [
+            Change::replace(&lines, span(1, 1), "B"),
+            Change::replace(&lines, span(3, 3), "D"),
+        ];
+        let expected =
+            "(Line: 1)\t     a\n(Line: 2)\t --- b\n(Line: 2)\t +++ B\n(Line: 3)\t     c\n\
+                        (Line: 4)\t --- d\n(Line: 4)\t +++ D\n(Line: 5)\t     e\n";
+        assert_eq!(expected, printed(contents, &changes, 1));
+
+        // the lines an adjacent change removes are shown removed, never as context
+        let changes = [
+            Change::insert(2, "x"),
+            Change::replace(&lines, span(2, 2), "C"),
+        ];
+        let expected =
+            "(Line: 1)\t     a\n(Line: 2)\t     b\n(Line: 3)\t +++ x\n(Line: 3)\t --- c\n\
+                        (Line: 4)\t +++ C\n(Line: 5)\t     d\n(Line: 6)\t     e\n";
+        assert_eq!(expected, printed(contents, &changes, 2));
+
+        // without context, only the changes are shown, and never a separator
+        let changes = [
+            Change::replace(&lines, span(1, 1), "B"),
+            Change::remove(&lines, span(6, 7)),
+        ];
+        let expected =
+            "(Line: 2)\t --- b\n(Line: 2)\t +++ B\n(Line: 7)\t --- g\n(Line: 8)\t --- h\n";
+        assert_eq!(expected, printed(contents, &changes, 0));
+
+        // context at the end of the file stops at its last line
+        let changes = [Change::remove(&lines, span(6, 7))];
+        let expected =
+            "(Line: 5)\t     e\n(Line: 6)\t     f\n(Line: 7)\t --- g\n(Line: 8)\t --- h\n";
+        assert_eq!(expected, printed(contents, &changes, 2));
+
+        // -A and -B override -C on their side of the change
+        let changes = [Change::replace(&lines, span(3, 3), "D")];
+        let display = DisplayOptions {
+            context: Some(2),
+            after_context: Some(0),
+            before_context: Some(1),
+            color: ColorWhen::Never,
+            ..Default::default()
+        };
+        let expected = "(Line: 3)\t     c\n(Line: 4)\t --- d\n(Line: 4)\t +++ D\n";
+        assert_eq!(
+            expected,
+            capture(true, || print_changes(contents, &changes, &display)).stdout
+        );
+
+        // every change of a pattern highlights what it matched
+        let matched = |span: LineSpan| Change {
+            pattern: Some("[bg]".to_owned()),
+            ..Change::replace(&lines, span, "x")
+        };
+        let changes = [matched(span(1, 1)), matched(span(6, 6))];
+        let display = DisplayOptions {
+            color: ColorWhen::Always,
+            ..Default::default()
+        };
+        let stdout = capture(true, || print_changes(contents, &changes, &display)).stdout;
+        assert_eq!(2, stdout.matches("\x1b[1;4m").count());
+    }
+
+    #[test]
+    fn test_apply_changes() {
+        let contents = "a\nb\nc\nd";
+        let lines: Vec<&str> = contents.split('\n').collect();
+        let changes = vec![
+            Change::insert(0, "start"),
+            Change::replace(&lines, LineSpan { start: 1, end: 2 }, "bc"),
+            Change::remove(&lines, LineSpan { start: 3, end: 3 }),
+        ];
+        assert_eq!("start\na\nbc\n", apply_changes(contents, &changes));
+    }
+
+    #[test]
+    fn test_changes_between() {
+        for (contents, mutated) in [
+            ("a\nb\nc\n", "a\nB\nc\nd\n"),
+            ("a\nb", "a\nb\n"),
+            ("a\nb", "a"),
+            ("", "a\n"),
+        ] {
+            let changes = changes_between(contents, mutated);
+            assert_eq!(mutated, apply_changes(contents, &changes));
+        }
+        assert_eq!(
+            vec![Change::insert(3, "d")],
+            changes_between("a\nb\nc\n", "a\nb\nc\nd\n")
+        );
+    }
+}
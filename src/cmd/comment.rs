@@ -0,0 +1,211 @@
+use std::path::Path;
+
+use super::{addressed_spans, change::Change, commit_changes, matcher::LineSpan, read_file};
+use crate::cli::parser::{WhereCommands, WriteOptions};
+
+/// How a file format marks a line as a comment, e.g. `#` or `<!-- -->`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CommentSyntax {
+    pub prefix: String,
+    pub suffix: Option<String>,
+}
+
+impl CommentSyntax {
+    /// Parses a user provided comment marker. A space separates the prefix from an optional
+    /// suffix, so `<!-- -->` comments out lines XML style.
+    pub fn new(marker: &str) -> CommentSyntax {
+        let marker = marker.trim();
+        match marker.split_once(char::is_whitespace) {
+            Some((prefix, suffix)) => CommentSyntax {
+                prefix: prefix.to_owned(),
+                suffix: Some(suffix.trim().to_owned()),
+            },
+            None => CommentSyntax {
+                prefix: marker.to_owned(),
+                suffix: None,
+            },
+        }
+    }
+
+    /// The comment syntaxes a file uses, inferred from its extension. The first is the one new
+    /// comments are written with. Unknown extensions are assumed to use `#`.
+    pub fn for_file(file_path: &str) -> Vec<CommentSyntax> {
+        let extension = Path::new(file_path)
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .unwrap_or_default()
+            .to_lowercase();
+        let markers: &[&str] = match extension.as_str() {
+            "ini" | "inf" | "reg" => &[";", "#"],
+            "jsonc" | "json5" | "js" | "ts" | "rs" | "c" | "h" | "cpp" | "hpp" | "java" | "go" => {
+                &["//"]
+            }
+            "xml" | "xsd" | "xsl" | "svg" | "html" | "xhtml" | "plist" => &["<!-- -->"],
+            "sql" | "lua" => &["--"],
+            _ => &["#"],
+        };
+        markers
+            .iter()
+            .map(|marker| CommentSyntax::new(marker))
+            .collect()
+    }
+
+    /// Resolves `--ignore-comments[=MARKER]` style options: an explicit marker wins, otherwise the
+    /// syntax is inferred from the file.
+    pub fn resolve(marker: Option<&str>, file_path: &str) -> Vec<CommentSyntax> {
+        match marker {
+            Some(marker) => vec![CommentSyntax::new(marker)],
+            None => CommentSyntax::for_file(file_path),
+        }
+    }
+
+    /// A line is a comment when, ignoring indentation, it starts with the prefix. Comments spanning
+    /// several lines are only recognised on their first line.
+    pub fn is_comment(&self, line: &str) -> bool {
+        line.trim_start().starts_with(self.prefix.as_str())
+    }
+
+    /// Comments out the line after its indentation. Blank lines and comments are left alone.
+    pub fn comment_out(&self, line: &str) -> Option<String> {
+        if line.trim().is_empty() || self.is_comment(line) {
+            return None;
+        }
+        let text = line.trim_start();
+        let indentation = &line[..line.len() - text.len()];
+        Some(match &self.suffix {
+            Some(suffix) => format!("{}{} {} {}", indentation, self.prefix, text, suffix),
+            None => format!("{}{} {}", indentation, self.prefix, text),
+        })
+    }
+
+    /// Strips the comment marker (and the space after it) while keeping the indentation. Lines
+    /// that aren't comments are left alone.
+    pub fn uncomment(&self, line: &str) -> Option<String> {
+        let commented = line.trim_start();
+        let indentation = &line[..line.len() - commented.len()];
+        let mut text = commented.strip_prefix(self.prefix.as_str())?;
+        text = text.strip_prefix(' ').unwrap_or(text);
+        if let Some(suffix) = &self.suffix {
+            text = text.trim_end();
+            text = text.strip_suffix(suffix.as_str()).unwrap_or(text);
+            text = text.strip_suffix(' ').unwrap_or(text);
+        }
+        Some(format!("{}{}", indentation, text))
+    }
+}
+
+pub fn comment_lines(
+    file_path: String,
+    address: &WhereCommands,
+    marker: Option<String>,
+    options: &WriteOptions,
+) {
+    let syntax = CommentSyntax::resolve(marker.as_deref(), &file_path).remove(0);
+    toggle_lines(file_path, address, options, |line| syntax.comment_out(line));
+}
+
+pub fn uncomment_lines(
+    file_path: String,
+    address: &WhereCommands,
+    marker: Option<String>,
+    options: &WriteOptions,
+) {
+    let syntaxes = CommentSyntax::resolve(marker.as_deref(), &file_path);
+    toggle_lines(file_path, address, options, |line| {
+        syntaxes.iter().find_map(|syntax| syntax.uncomment(line))
+    });
+}
+
+/// Rewrites every addressed line `toggle` returns a new line for.
+fn toggle_lines<F>(file_path: String, address: &WhereCommands, options: &WriteOptions, toggle: F)
+where
+    F: Fn(&str) -> Option<String>,
+{
+    let (contents, snapshot) = read_file(&file_path, options);
+    let lines: Vec<&str> = contents.split('\n').collect();
+    let changes: Vec<Change> = addressed_spans(&file_path, &contents, address)
+        .into_iter()
+        .flat_map(|span| span.start..=span.end)
+        .filter_map(|index| {
+            let toggled = toggle(lines[index])?;
+            let span = LineSpan {
+                start: index,
+                end: index,
+            };
+            Some(Change::replace(&lines, span, &toggled))
+        })
+        .collect();
+    commit_changes(file_path, &contents, snapshot, &changes, options);
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Read, Write};
+    use tempfile::Builder;
+
+    use crate::cli::parser::{
+        ExpectedMatches, LineMutation, MatchOptions, PatternMutation, WhereCommands, WriteOptions,
+    };
+    use crate::cmd::comment::{comment_lines, uncomment_lines, CommentSyntax};
+
+    #[test]
+    fn test_syntax_from_extension() {
+        assert_eq!(
+            vec![CommentSyntax::new("#")],
+            CommentSyntax::for_file("a.toml")
+        );
+        assert_eq!(
+            vec![CommentSyntax::new(";"), CommentSyntax::new("#")],
+            CommentSyntax::for_file("/etc/php.INI")
+        );
+        let xml = &CommentSyntax::for_file("pom.xml")[0];
+        assert_eq!("<!--", xml.prefix);
+        assert_eq!(Some("-->".to_owned()), xml.suffix);
+        assert!(xml.is_comment("  <!-- <dependency> -->"));
+    }
+
+    #[test]
+    fn test_comment_pattern() {
+        // create test file
+        let mut file1 = Builder::new().suffix(".toml").tempfile().unwrap();
+        file1
+            .write_all("[table]\n  entry_1=1\n  entry_2=2".as_bytes())
+            .unwrap();
+        file1.flush().unwrap();
+        let file_path = file1.path().to_str().unwrap().to_owned();
+        // mutate file
+        let address = WhereCommands::OverwritePattern(PatternMutation {
+            pattern: "^\\s*entry_".to_owned(),
+            ignore_comments: None,
+            options: MatchOptions::default(),
+            expected: ExpectedMatches::default(),
+        });
+        comment_lines(file_path, &address, None, &WriteOptions::default());
+        // read in file
+        let mut mutated_contents = String::new();
+        let mut file2 = file1.reopen().unwrap();
+        file2.read_to_string(&mut mutated_contents).unwrap();
+        // compare results
+        assert_eq!("[table]\n  # entry_1=1\n  # entry_2=2", mutated_contents);
+    }
+
+    #[test]
+    fn test_uncomment_line() {
+        // create test file
+        let mut file1 = Builder::new().suffix(".xml").tempfile().unwrap();
+        file1
+            .write_all("<a>\n  <!-- <b>1</b> -->\n</a>".as_bytes())
+            .unwrap();
+        file1.flush().unwrap();
+        let file_path = file1.path().to_str().unwrap().to_owned();
+        // mutate file
+        let address = WhereCommands::AtLine(LineMutation { line_number: 2 });
+        uncomment_lines(file_path, &address, None, &WriteOptions::default());
+        // read in file
+        let mut mutated_contents = String::new();
+        let mut file2 = file1.reopen().unwrap();
+        file2.read_to_string(&mut mutated_contents).unwrap();
+        // compare results
+        assert_eq!("<a>\n  <b>1</b>\n</a>", mutated_contents);
+    }
+}
@@ -0,0 +1,334 @@
+use regex::RegexSet;
+use std::collections::{BTreeMap, BTreeSet};
+
+use super::{
+    change::Change,
+    commit_changes, find_line, line_count,
+    matcher::{LineMatcher, LineSpan},
+    output::outln,
+    pattern_not_found, read_file,
+};
+use crate::cli::parser::{AddCommands, PatternMutation, WhereCommands, WriteOptions};
+
+/// An operation of a recipe, making the same changes as the `add` or `remove` command it's
+/// written like.
+#[derive(Debug, Clone)]
+pub enum Operation {
+    Add { entry: String, address: AddCommands },
+    Remove { address: WhereCommands },
+}
+
+impl Operation {
+    /// The pattern the operation finds its lines with, if it has one.
+    fn pattern(&self) -> Option<&PatternMutation> {
+        match self {
+            Operation::Add {
+                address: AddCommands::Where(address),
+                ..
+            }
+            | Operation::Remove { address } => address.pattern(),
+            Operation::Add { .. } => None,
+        }
+    }
+}
+
+/// Applies all of the operations to the file at once, writing it a single time. The patterns
+/// matched line by line share one `RegexSet`, so the file is scanned once however many operations
+/// there are. When operations change the same lines, the one listed first wins.
+pub fn apply_operations(file_path: String, operations: &[Operation], options: &WriteOptions) {
+    let (contents, snapshot) = read_file(&file_path, options);
+    let lines: Vec<&str> = contents.split('\n').collect();
+    let matchers: Vec<Option<LineMatcher>> = operations
+        .iter()
+        .map(|operation| operation_matcher(&file_path, operation))
+        .collect();
+    let changes: Vec<(usize, Change)> = find_all_spans(&contents, &matchers)
+        .into_iter()
+        .zip(operations.iter().zip(&matchers))
+        .enumerate()
+        .flat_map(|(index, (spans, (operation, matcher)))| {
+            operation_changes(&contents, &lines, operation, spans)
+                .into_iter()
+                .map(move |change| match matcher {
+                    Some(matcher) => (index, change.matched_by(matcher)),
+                    None => (index, change),
+                })
+        })
+        .collect();
+    let changes = resolve_conflicts(changes);
+    commit_changes(file_path, &contents, snapshot, &changes, options);
+}
+
+/// The matcher for the pattern of an operation, or `None` for operations without a pattern.
+fn operation_matcher(file_path: &str, operation: &Operation) -> Option<LineMatcher> {
+    operation
+        .pattern()
+        .map(|pattern_mut| LineMatcher::for_file(pattern_mut, file_path))
+}
+
+/// The spans the matcher of each operation selects, or `None` for operations without a pattern.
+fn find_all_spans(contents: &str, matchers: &[Option<LineMatcher>]) -> Vec<Option<Vec<LineSpan>>> {
+    // multiline patterns need the whole file, every other one is matched in the single pass
+    let batched: Vec<&LineMatcher> = matchers
+        .iter()
+        .flatten()
+        .filter(|matcher| !matcher.is_multiline())
+        .collect();
+    let set = RegexSet::new(batched.iter().map(|matcher| matcher.pattern())).unwrap();
+    let mut matched = vec![vec![false; contents.split('\n').count()]; batched.len()];
+    for (index, line) in contents.split('\n').enumerate() {
+        for which in set.matches(line).into_iter() {
+            matched[which][index] = true;
+        }
+    }
+
+    let mut matched = matched.into_iter();
+    matchers
+        .iter()
+        .map(|matcher| {
+            matcher.as_ref().map(|matcher| {
+                if matcher.is_multiline() {
+                    matcher.find_spans(contents)
+                } else {
+                    matcher.spans_from_lines(contents, &matched.next().unwrap())
+                }
+            })
+        })
+        .collect()
+}
+
+/// The changes a single operation makes, the same ones the `add` and `remove` commands make.
+fn operation_changes(
+    contents: &str,
+    lines: &[&str],
+    operation: &Operation,
+    spans: Option<Vec<LineSpan>>,
+) -> Vec<Change> {
+    let is_pattern = spans.is_some();
+    let spans = spans.unwrap_or_default().into_iter();
+    let single = |index: usize| LineSpan {
+        start: index,
+        end: index,
+    };
+    let numbered = |line_no: isize| single(find_line(contents, line_no));
+
+    let changes: Vec<Change> = match operation {
+        Operation::Add { entry, address } => match address {
+            AddCommands::Where(WhereCommands::AfterPattern(_)) => spans
+                .map(|span| Change::insert(span.end + 1, entry).matching(span))
+                .collect(),
+            AddCommands::Where(WhereCommands::BeforePattern(_)) => spans
+                .map(|span| Change::insert(span.start, entry).matching(span))
+                .collect(),
+            AddCommands::Where(WhereCommands::OverwritePattern(_)) => spans
+                .map(|span| Change::replace(lines, span, entry))
+                .collect(),
+            AddCommands::Where(WhereCommands::AtLine(line_mut)) => {
+                vec![Change::insert(numbered(line_mut.line_number).start, entry)]
+            }
+            AddCommands::Where(WhereCommands::OverwriteLine(line_mut)) => {
+                vec![Change::replace(
+                    lines,
+                    numbered(line_mut.line_number),
+                    entry,
+                )]
+            }
+            AddCommands::Append => vec![Change::insert(line_count(contents), entry)],
+            AddCommands::Prepend => vec![Change::insert(0, entry)],
+        },
+        Operation::Remove { address } => match address {
+            WhereCommands::AfterPattern(_) => spans
+                .filter(|span| span.end + 1 < lines.len())
+                .map(|span| Change::remove(lines, single(span.end + 1)).matching(span))
+                .collect(),
+            WhereCommands::BeforePattern(_) => spans
+                .filter(|span| span.start > 0)
+                .map(|span| Change::remove(lines, single(span.start - 1)).matching(span))
+                .collect(),
+            WhereCommands::OverwritePattern(_) => {
+                spans.map(|span| Change::remove(lines, span)).collect()
+            }
+            WhereCommands::AtLine(line_mut) | WhereCommands::OverwriteLine(line_mut) => {
+                vec![Change::remove(lines, numbered(line_mut.line_number))]
+            }
+        },
+    };
+
+    let allows_no_match = operation
+        .pattern()
+        .is_some_and(|pattern_mut| pattern_mut.expected.allows_no_match());
+    if is_pattern && changes.is_empty() && !allows_no_match {
+        pattern_not_found();
+    }
+    changes
+}
+
+/// Drops every change that collides with a change of an operation listed before it, and sorts the
+/// rest the way `apply_changes` needs them. Changes collide when they remove any of the same lines,
+/// or when one inserts in between lines the other removes.
+fn resolve_conflicts(changes: Vec<(usize, Change)>) -> Vec<Change> {
+    // the lines removed by the changes kept so far, by the line they start on
+    let mut removed: BTreeMap<usize, usize> = BTreeMap::new();
+    let mut inserted: BTreeSet<usize> = BTreeSet::new();
+    let mut kept: Vec<Change> = Vec::new();
+    for (operation, change) in changes {
+        let start = change.line;
+        let end = change.line + change.removed.len();
+        let inside_removal = |line: usize| {
+            removed
+                .range(..line)
+                .next_back()
+                .is_some_and(|(_, &removed_end)| line < removed_end)
+        };
+        let collides = if change.removed.is_empty() {
+            inside_removal(start)
+        } else {
+            // the kept removals don't overlap, so of those starting before this one ends only the
+            // last can reach into it
+            removed
+                .range(..end)
+                .next_back()
+                .is_some_and(|(_, &removed_end)| removed_end > start)
+                || inserted.range(start + 1..end).next().is_some()
+        };
+        if collides {
+            outln!(
+                "WARNING: Skipped the change operation {} makes at line {}, an earlier operation already changes it.",
+                operation + 1,
+                start + 1
+            );
+            continue;
+        }
+
+        if change.removed.is_empty() {
+            inserted.insert(start);
+        } else {
+            removed.insert(start, end);
+        }
+        kept.push(change);
+    }
+
+    // inserts go before a removal starting on the same line, and otherwise keep their order
+    kept.sort_by_key(|change| (change.line, !change.removed.is_empty()));
+    kept
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Read, Write};
+    use tempfile::NamedTempFile;
+
+    use crate::cli::parser::{
+        AddCommands, ExpectedMatches, LineMutation, MatchOptions, PatternMutation, WhereCommands,
+        WriteOptions,
+    };
+    use crate::cmd::engine::{apply_operations, Operation};
+
+    const FAUX_FILE: &str = "[table]\n\
+    [[subtable1]]\n\
+    entry_1=\"one\"\n\
+    entry_2=\"two\"\n\
+    \n\
+    [[subtable2]]\n\
+    entry_4=\"four\"";
+
+    fn pattern(pattern: &str, options: MatchOptions) -> PatternMutation {
+        PatternMutation {
+            pattern: pattern.to_owned(),
+            ignore_comments: None,
+            options,
+            expected: ExpectedMatches::default(),
+        }
+    }
+
+    #[test]
+    fn test_apply_operations() {
+        // create test file
+        let mut file1 = NamedTempFile::new().unwrap();
+        file1.write_all(FAUX_FILE.as_bytes()).unwrap();
+        file1.flush().unwrap();
+        let file_path = file1.path().to_str().unwrap().to_owned();
+        // mutate file
+        let operations = vec![
+            Operation::Add {
+                entry: "entry_3=\"three\"".to_owned(),
+                address: AddCommands::Where(WhereCommands::AfterPattern(pattern(
+                    "^ENTRY_2",
+                    MatchOptions {
+                        ignore_case: true,
+                        ..Default::default()
+                    },
+                ))),
+            },
+            Operation::Remove {
+                address: WhereCommands::OverwritePattern(pattern("^$", MatchOptions::default())),
+            },
+            Operation::Add {
+                entry: "[[subtable3]]".to_owned(),
+                address: AddCommands::Append,
+            },
+            // collides with the overwrite of the empty line above, so it's skipped
+            Operation::Add {
+                entry: "# nothing".to_owned(),
+                address: AddCommands::Where(WhereCommands::OverwriteLine(LineMutation {
+                    line_number: 5,
+                })),
+            },
+            Operation::Add {
+                entry: "entry_0=\"zero\"".to_owned(),
+                address: AddCommands::Where(WhereCommands::BeforePattern(pattern(
+                    "entry_1",
+                    MatchOptions {
+                        word: true,
+                        ..Default::default()
+                    },
+                ))),
+            },
+        ];
+        apply_operations(file_path, &operations, &WriteOptions::default());
+        // read in file
+        let mut mutated_contents = String::new();
+        let mut file2 = file1.reopen().unwrap();
+        file2.read_to_string(&mut mutated_contents).unwrap();
+        // compare results
+        let expected = "[table]\n\
+        [[subtable1]]\n\
+        entry_0=\"zero\"\n\
+        entry_1=\"one\"\n\
+        entry_2=\"two\"\n\
+        entry_3=\"three\"\n\
+        [[subtable2]]\n\
+        entry_4=\"four\"\n\
+        [[subtable3]]";
+        assert_eq!(expected, mutated_contents);
+    }
+
+    #[test]
+    fn test_overlapping_multiline_removals() {
+        // create test file
+        let mut file1 = NamedTempFile::new().unwrap();
+        file1.write_all("a\nb\nc\nd\ne\n".as_bytes()).unwrap();
+        file1.flush().unwrap();
+        let file_path = file1.path().to_str().unwrap().to_owned();
+        // mutate file, the second removal starting inside the first
+        let multiline = || MatchOptions {
+            multiline: true,
+            ..Default::default()
+        };
+        let operations = vec![
+            Operation::Remove {
+                address: WhereCommands::OverwritePattern(pattern("b\nc", multiline())),
+            },
+            Operation::Remove {
+                address: WhereCommands::OverwritePattern(pattern("c\nd", multiline())),
+            },
+        ];
+        apply_operations(file_path, &operations, &WriteOptions::default());
+        // read in file
+        let mut mutated_contents = String::new();
+        let mut file2 = file1.reopen().unwrap();
+        file2.read_to_string(&mut mutated_contents).unwrap();
+        // compare results
+        assert_eq!("a\nd\ne\n", mutated_contents);
+    }
+}
@@ -0,0 +1,187 @@
+use std::{env, fs};
+
+use super::output::{self, outln};
+
+use crate::cli::parser::EntryOptions;
+
+/// The entry to add: the one given on the command line or the contents of `--entry-file`, with
+/// its variables substituted for `--expand-env`.
+pub fn resolve_entry(entry: Option<String>, options: &EntryOptions) -> Option<String> {
+    let entry = match &options.entry_file {
+        Some(path) => read_entry_file(path),
+        None => entry?,
+    };
+    if !options.expand_env {
+        return Some(entry);
+    }
+
+    // later `--var`s win over earlier ones, and all of them over the environment
+    let lookup = |name: &str| {
+        options
+            .vars
+            .iter()
+            .rev()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.clone())
+            .or_else(|| env::var(name).ok())
+    };
+    match expand(&entry, lookup) {
+        Ok(expanded) => Some(expanded),
+        Err(message) => {
+            outln!("WARNING: {} Nothing was changed.", message);
+            output::exit(exitcode::DATAERR);
+        }
+    }
+}
+
+fn read_entry_file(path: &str) -> String {
+    let Ok(contents) = fs::read_to_string(path) else {
+        outln!(
+            "WARNING: The entry file {} could not be read. Please check the path and try again.",
+            path
+        );
+        output::exit(exitcode::NOINPUT);
+    };
+    // the new line editors end a file with isn't part of the entry
+    let entry = contents.strip_suffix('\n').unwrap_or(&contents);
+    entry.strip_suffix('\r').unwrap_or(entry).to_owned()
+}
+
+/// Substitutes `${NAME}` and `${NAME:-default}` with what `lookup` finds for `NAME`. Like in a
+/// shell, the default is used when the variable is unset or empty. `$${` is a literal `${` and any
+/// other `$` is left alone, so `$` inside TOML strings and the like survive.
+fn expand<F>(entry: &str, lookup: F) -> Result<String, String>
+where
+    F: Fn(&str) -> Option<String>,
+{
+    let mut expanded = String::with_capacity(entry.len());
+    let mut rest = entry;
+    while let Some(start) = rest.find("${") {
+        if rest[..start].ends_with('$') {
+            expanded.push_str(&rest[..start - 1]);
+            expanded.push_str("${");
+            rest = &rest[start + 2..];
+            continue;
+        }
+        expanded.push_str(&rest[..start]);
+
+        let Some(length) = rest[start..].find('}') else {
+            return Err(format!(
+                "`{}` is missing its closing brace.",
+                &rest[start..]
+            ));
+        };
+        let expression = &rest[start + 2..start + length];
+        let (name, default) = match expression.split_once(":-") {
+            Some((name, default)) => (name, Some(default)),
+            None => (expression, None),
+        };
+        if !is_variable_name(name) {
+            return Err(format!("`${{{}}}` is not a valid variable.", expression));
+        }
+        let value = match (lookup(name), default) {
+            (Some(value), Some(default)) if value.is_empty() => default.to_owned(),
+            (Some(value), _) => value,
+            (None, Some(default)) => default.to_owned(),
+            (None, None) => {
+                return Err(format!(
+                    "The variable {} is not set. Set it, pass --var {}=..., or give a default with ${{{}:-default}}.",
+                    name, name, name
+                ))
+            }
+        };
+        expanded.push_str(&value);
+        rest = &rest[start + length + 1..];
+    }
+    expanded.push_str(rest);
+    Ok(expanded)
+}
+
+fn is_variable_name(name: &str) -> bool {
+    name.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, io::Write};
+    use tempfile::NamedTempFile;
+
+    use crate::cli::parser::{EntryOptions, MatchOptions, WriteOptions};
+    use crate::cmd::{
+        add::add_after_pattern,
+        entry::{expand, resolve_entry},
+        journal::{isolated_journal, undo},
+        matcher::LineMatcher,
+    };
+
+    fn lookup(name: &str) -> Option<String> {
+        match name {
+            "PORT" => Some("8080".to_owned()),
+            "EMPTY" => Some(String::new()),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn test_expand() {
+        assert_eq!(
+            Ok("port = 8080 # $HOME".to_owned()),
+            expand("port = ${PORT} # $HOME", lookup)
+        );
+        assert_eq!(
+            Ok("tag = \"latest\", host = \"\"".to_owned()),
+            expand("tag = \"${TAG:-latest}\", host = \"${EMPTY}\"", lookup)
+        );
+        assert_eq!(
+            Ok("a=${PORT} b=x".to_owned()),
+            expand("a=$${PORT} b=${EMPTY:-x}", lookup)
+        );
+    }
+
+    #[test]
+    fn test_expand_errors() {
+        assert!(expand("${TAG}", lookup)
+            .unwrap_err()
+            .contains("TAG is not set"));
+        assert!(expand("${PORT", lookup).is_err());
+        assert!(expand("${1PORT}", lookup).is_err());
+    }
+
+    #[test]
+    fn test_multi_line_entry_file() {
+        let _journal = isolated_journal();
+        // create test file and entry file
+        let mut file1 = NamedTempFile::new().unwrap();
+        file1.write_all("[a]\n[b]\n".as_bytes()).unwrap();
+        file1.flush().unwrap();
+        let file_path = file1.path().to_str().unwrap().to_owned();
+        let mut entry_file = NamedTempFile::new().unwrap();
+        entry_file.write_all("x = 1\ny = 2\n".as_bytes()).unwrap();
+        entry_file.flush().unwrap();
+        let entry_options = EntryOptions {
+            entry_file: Some(entry_file.path().to_str().unwrap().to_owned()),
+            ..Default::default()
+        };
+        // mutate file
+        let entry = resolve_entry(None, &entry_options).unwrap();
+        let options = WriteOptions {
+            journal: true,
+            ..Default::default()
+        };
+        add_after_pattern(
+            file_path.clone(),
+            &LineMatcher::new("^\\[a]", &MatchOptions::default()),
+            entry,
+            &options,
+        );
+        // compare results
+        assert_eq!(
+            "[a]\nx = 1\ny = 2\n[b]\n",
+            fs::read_to_string(&file_path).unwrap()
+        );
+        // every line of the entry is undone
+        undo(1, false, &options);
+        assert_eq!("[a]\n[b]\n", fs::read_to_string(&file_path).unwrap());
+    }
+}
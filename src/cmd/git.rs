@@ -0,0 +1,230 @@
+use std::{
+    path::Path,
+    process::{Command, Output},
+    sync::Mutex,
+};
+
+use super::{
+    output::{self, outln},
+    DIRTY_FILE,
+};
+
+/// Runs git on a single file from the directory the file is in, so the repository it belongs to
+/// is the one used no matter where mute runs from. Nothing here talks to a remote.
+fn git(file_path: &str, args: &[&str]) -> Output {
+    let path = Path::new(file_path);
+    let directory = path
+        .parent()
+        .filter(|parent| !parent.as_os_str().is_empty())
+        .unwrap_or(Path::new("."));
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(directory)
+        .args(args)
+        .arg("--")
+        .arg(path.file_name().unwrap_or(path.as_os_str()))
+        .output();
+    match output {
+        Ok(output) => output,
+        Err(error) => {
+            outln!(
+                "WARNING: Could not run git: {}. Nothing was changed.",
+                error
+            );
+            output::exit(exitcode::UNAVAILABLE);
+        }
+    }
+}
+
+/// Whether the file is in git's index. Untracked and ignored files aren't, and neither are files
+/// outside of a repository.
+pub fn is_tracked(file_path: &str) -> bool {
+    git(file_path, &["ls-files", "--error-unmatch"])
+        .status
+        .success()
+}
+
+/// The short status of the file, empty when it's committed as it is, and `!!` when it's ignored.
+/// Errs with git's complaint when the file isn't in a repository.
+fn status(file_path: &str) -> Result<String, String> {
+    let output = git(file_path, &["status", "--porcelain", "--ignored"]);
+    if output.status.success() {
+        Ok(String::from_utf8_lossy(&output.stdout)
+            .trim_end()
+            .to_owned())
+    } else {
+        Err(String::from_utf8_lossy(&output.stderr).trim().to_owned())
+    }
+}
+
+/// Exits for `--git-clean` unless the file is committed as it is, so mute's changes never get
+/// mixed up with uncommitted work. A file that doesn't exist yet and isn't tracked counts as clean.
+pub fn ensure_clean(file_path: &str) {
+    match status(file_path) {
+        Ok(status) if status.is_empty() => {}
+        // an ignored file is just as untracked, git only leaves it out of its status by default
+        Ok(status) if status.starts_with("??") || status.starts_with("!!") => {
+            outln!(
+                "WARNING: {} is not tracked by git, so its changes couldn't be undone with git. Commit it first. Nothing was changed.",
+                file_path
+            );
+            output::exit(DIRTY_FILE);
+        }
+        Ok(status) => {
+            outln!(
+                "WARNING: {} has uncommitted changes ({}). Commit or stash them first. Nothing was changed.",
+                file_path, status
+            );
+            output::exit(DIRTY_FILE);
+        }
+        Err(error) => {
+            outln!(
+                "WARNING: {} is not in a git repository ({}). Nothing was changed.",
+                file_path,
+                error
+            );
+            output::exit(exitcode::USAGE);
+        }
+    }
+}
+
+/// Stages the mutated file for `--git-add`. Files mutated at the same time are staged one after
+/// the other, since git refuses to update an index another git is updating.
+pub fn stage(file_path: &str) {
+    static INDEX: Mutex<()> = Mutex::new(());
+    let output = {
+        let _index = INDEX.lock().unwrap_or_else(|error| error.into_inner());
+        git(file_path, &["add"])
+    };
+    if !output.status.success() {
+        outln!(
+            "WARNING: {} was changed but could not be staged: {}",
+            file_path,
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+        output::exit(exitcode::UNAVAILABLE);
+    }
+}
+
+/// Drops the files git doesn't track for `--git-tracked-only`, saying which ones were skipped.
+pub fn tracked_files(files: Vec<String>) -> Vec<String> {
+    files
+        .into_iter()
+        .filter(|file| {
+            let tracked = is_tracked(file);
+            if !tracked {
+                outln!("WARNING: Skipping {}, it is not tracked by git.", file);
+            }
+            tracked
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, process::Command};
+
+    use crate::cli::parser::WriteOptions;
+    use crate::cmd::{
+        add::append_entry,
+        git::{ensure_clean, is_tracked, stage, status},
+        output::capture,
+        DIRTY_FILE,
+    };
+
+    #[test]
+    fn test_git_status() {
+        // create test repository
+        let dir = tempfile::tempdir().unwrap();
+        let run = |args: &[&str]| {
+            let status = Command::new("git")
+                .arg("-C")
+                .arg(dir.path())
+                .args(["-c", "user.name=mute", "-c", "user.email=mute@localhost"])
+                .args(args)
+                .status()
+                .unwrap();
+            assert!(status.success());
+        };
+        run(&["init", "-q"]);
+        let file = dir.path().join("app.toml");
+        let file_path = file.to_str().unwrap();
+        fs::write(&file, "a\n").unwrap();
+        // untracked files are dirty
+        assert!(!is_tracked(file_path));
+        assert_eq!(Ok("?? app.toml".to_owned()), status(file_path));
+        // staged files are tracked, but still dirty
+        stage(file_path);
+        assert!(is_tracked(file_path));
+        assert_eq!(Ok("A  app.toml".to_owned()), status(file_path));
+        // committed files are clean until they change
+        run(&["commit", "-q", "-m", "init"]);
+        assert_eq!(Ok(String::new()), status(file_path));
+        fs::write(&file, "b\n").unwrap();
+        assert_eq!(Ok(" M app.toml".to_owned()), status(file_path));
+    }
+
+    #[test]
+    fn test_ensure_clean() {
+        // create test repository
+        let dir = tempfile::tempdir().unwrap();
+        let run = |args: &[&str]| {
+            let status = Command::new("git")
+                .arg("-C")
+                .arg(dir.path())
+                .args(["-c", "user.name=mute", "-c", "user.email=mute@localhost"])
+                .args(args)
+                .status()
+                .unwrap();
+            assert!(status.success());
+        };
+        run(&["init", "-q"]);
+        let file = dir.path().join("app.toml");
+        let file_path = file.to_str().unwrap().to_owned();
+        fs::write(&file, "a\n").unwrap();
+        // untracked files are refused with a message of their own
+        let outcome = capture(true, || ensure_clean(&file_path));
+        assert_eq!(DIRTY_FILE, outcome.code);
+        assert!(outcome.stdout.contains("is not tracked by git"));
+        // and so are ignored ones, which git leaves out of its status
+        let ignored = dir.path().join("app.log");
+        fs::write(dir.path().join(".gitignore"), "*.log\n").unwrap();
+        fs::write(&ignored, "a\n").unwrap();
+        let outcome = capture(true, || ensure_clean(ignored.to_str().unwrap()));
+        assert_eq!(DIRTY_FILE, outcome.code);
+        assert!(outcome.stdout.contains("app.log is not tracked by git"));
+        // committed files pass until they change
+        run(&["add", "app.toml"]);
+        run(&["commit", "-q", "-m", "init"]);
+        assert_eq!(0, capture(true, || ensure_clean(&file_path)).code);
+        fs::write(&file, "b\n").unwrap();
+        let outcome = capture(true, || ensure_clean(&file_path));
+        assert_eq!(DIRTY_FILE, outcome.code);
+        assert!(outcome
+            .stdout
+            .contains("has uncommitted changes ( M app.toml)"));
+        // only writing is refused, dry-runs and checks still report the change
+        let mutate = |options: WriteOptions| {
+            let options = WriteOptions {
+                git_clean: true,
+                ..options
+            };
+            capture(true, || {
+                append_entry(file_path.clone(), "c".to_owned(), &options)
+            })
+        };
+        let dry_run = mutate(WriteOptions {
+            dry_run: true,
+            ..Default::default()
+        });
+        assert_eq!(0, dry_run.code);
+        assert!(dry_run.stdout.contains("+++ c"));
+        let check = mutate(WriteOptions {
+            check: true,
+            ..Default::default()
+        });
+        assert_eq!(1, check.code);
+        assert_eq!(DIRTY_FILE, mutate(WriteOptions::default()).code);
+        assert_eq!("b\n", fs::read_to_string(&file).unwrap());
+    }
+}
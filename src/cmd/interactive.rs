@@ -0,0 +1,155 @@
+use std::io::{self, BufRead, IsTerminal, Write};
+
+use super::{
+    change::{print_changes, Change},
+    line_count,
+    matcher::LineSpan,
+    output::{self, outln},
+};
+use crate::cli::parser::DisplayOptions;
+
+/// How many unchanged lines are shown before and after each change.
+const CONTEXT_LINES: usize = 3;
+
+/// Asks about every change in turn and returns the ones that were accepted. Exits straight away
+/// when stdin isn't a terminal, as there'd be nobody to answer.
+pub fn confirm_changes(
+    contents: &str,
+    changes: &[Change],
+    display: &DisplayOptions,
+) -> Vec<Change> {
+    if !io::stdin().is_terminal() {
+        outln!(
+            "WARNING: --interactive needs a terminal to ask for confirmation. Nothing was changed."
+        );
+        output::exit(exitcode::USAGE);
+    }
+    let accepted = choose_changes(contents, changes, display, &mut io::stdin().lock());
+    if accepted.is_empty() {
+        outln!("No changes were accepted, so the file was left alone.");
+    }
+    accepted
+}
+
+fn choose_changes<R: BufRead>(
+    contents: &str,
+    changes: &[Change],
+    display: &DisplayOptions,
+    input: &mut R,
+) -> Vec<Change> {
+    // changes that leave their lines as they were aren't worth asking about
+    let changes: Vec<&Change> = changes
+        .iter()
+        .filter(|change| change.removed != change.added)
+        .collect();
+    let mut accepted = Vec::new();
+    for (index, change) in changes.iter().enumerate() {
+        print_hunk(contents, change, display);
+        loop {
+            print!(
+                "({}/{}) Apply this change [y,n,a,q,?]? ",
+                index + 1,
+                changes.len()
+            );
+            io::stdout().flush().unwrap();
+            let mut answer = String::new();
+            // running out of input answers no to everything that's left
+            if input.read_line(&mut answer).unwrap() == 0 {
+                return accepted;
+            }
+            match answer.trim() {
+                "y" => {
+                    accepted.push((*change).clone());
+                    break;
+                }
+                "n" => break,
+                "a" => {
+                    accepted.extend(changes[index..].iter().map(|&change| change.clone()));
+                    return accepted;
+                }
+                "q" => return accepted,
+                _ => outln!(
+                    "y - apply this change\n\
+                    n - skip this change\n\
+                    a - apply this change and all the ones after it\n\
+                    q - skip this change and all the ones after it"
+                ),
+            }
+        }
+    }
+    accepted
+}
+
+/// Prints a change like a dry-run does, between the unchanged lines around it. Every line is
+/// numbered as in the file before any of the changes.
+fn print_hunk(contents: &str, change: &Change, display: &DisplayOptions) {
+    let lines: Vec<&str> = contents.split('\n').take(line_count(contents)).collect();
+    let changed = LineSpan {
+        start: change.line,
+        end: change.line + change.removed.len(),
+    };
+    let shown = change.matched.map_or(changed, |matched| LineSpan {
+        start: changed.start.min(matched.start),
+        end: changed.end.max(matched.end + 1),
+    });
+
+    let first = shown.start.saturating_sub(CONTEXT_LINES);
+    let last = shown.end + CONTEXT_LINES;
+    let numbered = lines.iter().enumerate();
+
+    outln!();
+    for (index, line) in numbered.clone().take(shown.start).skip(first) {
+        outln!("(Line: {})\t     {}", index + 1, line);
+    }
+    // the context is printed here, numbered the same way as the rest of the hunk
+    let display = DisplayOptions {
+        color: display.color,
+        ..Default::default()
+    };
+    print_changes(contents, std::slice::from_ref(change), &display);
+    for (index, line) in numbered.take(last).skip(shown.end) {
+        outln!("(Line: {})\t     {}", index + 1, line);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use crate::cli::parser::DisplayOptions;
+    use crate::cmd::change::Change;
+    use crate::cmd::interactive::choose_changes;
+    use crate::cmd::matcher::LineSpan;
+
+    #[test]
+    fn test_choose_changes() {
+        let contents = "a\nb\nc\nd";
+        let lines: Vec<&str> = contents.split('\n').collect();
+        let changes: Vec<Change> = (0..4)
+            .map(|index| {
+                let span = LineSpan {
+                    start: index,
+                    end: index,
+                };
+                Change::replace(&lines, span, "x")
+            })
+            .collect();
+
+        let display = DisplayOptions::default();
+        let accepted = choose_changes(
+            contents,
+            &changes,
+            &display,
+            &mut Cursor::new("y\nhuh\nn\na\n"),
+        );
+        assert_eq!(vec![0, 2, 3], lines_of(&accepted));
+        let accepted = choose_changes(contents, &changes, &display, &mut Cursor::new("n\ny\nq\n"));
+        assert_eq!(vec![1], lines_of(&accepted));
+        let accepted = choose_changes(contents, &changes, &display, &mut Cursor::new("y\n"));
+        assert_eq!(vec![0], lines_of(&accepted));
+    }
+
+    fn lines_of(changes: &[Change]) -> Vec<usize> {
+        changes.iter().map(|change| change.line).collect()
+    }
+}
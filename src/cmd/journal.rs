@@ -0,0 +1,469 @@
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    env,
+    fs::{self, File, OpenOptions},
+    io::{Read, Write},
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use super::{
+    change::{apply_changes, Change},
+    content_hash, is_writing,
+    output::{self, outln},
+    print_changes, print_hashes, read_file, write_contents, WOULD_CHANGE,
+};
+use crate::cli::parser::WriteOptions;
+
+/// An applied mutation, recorded so `undo` can revert it.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct JournalEntry {
+    /// Seconds since the unix epoch.
+    pub time: u64,
+    pub file: String,
+    pub before: String,
+    pub after: String,
+    /// The changes turning the mutated file back into the original, numbered against the mutated file.
+    pub reverse: Vec<Change>,
+}
+
+/// `$XDG_STATE_HOME/mute/journal.jsonl`, falling back to `~/.local/state` like the XDG spec does.
+fn journal_path() -> Option<PathBuf> {
+    let state_home = env::var_os("XDG_STATE_HOME")
+        .filter(|state_home| !state_home.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| Path::new(&home).join(".local/state")))?;
+    Some(state_home.join("mute").join("journal.jsonl"))
+}
+
+/// Records the changes along with the hashes of the file before and after them.
+pub fn record(file_path: &str, before: String, after: String, changes: &[Change]) {
+    let Some(path) = journal_path() else {
+        outln!("WARNING: Neither XDG_STATE_HOME nor HOME is set, so this change can't be undone.");
+        return;
+    };
+    let entry = JournalEntry {
+        time: SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |since| since.as_secs()),
+        file: fs::canonicalize(file_path)
+            .map_or(file_path.to_owned(), |path| path.display().to_string()),
+        before,
+        after,
+        reverse: reverse_changes(changes),
+    };
+
+    let line = serde_json::to_string(&entry).unwrap() + "\n";
+    locked_journal(&path).write_all(line.as_bytes()).unwrap();
+}
+
+/// Opens the journal for appending, creating it if needed, and locks it so no other mute run
+/// appends to it or rewrites it until the returned file is dropped.
+fn locked_journal(path: &Path) -> File {
+    fs::create_dir_all(path.parent().unwrap()).unwrap();
+    let journal = OpenOptions::new()
+        .read(true)
+        .append(true)
+        .create(true)
+        .open(path)
+        .unwrap();
+    journal.lock().unwrap();
+    journal
+}
+
+/// Swaps what each change adds and removes, renumbering them against the mutated file.
+pub fn reverse_changes(changes: &[Change]) -> Vec<Change> {
+    let mut offset: isize = 0;
+    changes
+        .iter()
+        .map(|change| {
+            let reverse = Change {
+                line: (change.line as isize + offset) as usize,
+                removed: change.added.clone(),
+                added: change.removed.clone(),
+                matched: None,
+                pattern: None,
+            };
+            offset += change.added.len() as isize - change.removed.len() as isize;
+            reverse
+        })
+        .collect()
+}
+
+fn read_journal() -> Vec<JournalEntry> {
+    let Some(mut journal) = journal_path().and_then(|path| File::open(path).ok()) else {
+        return Vec::new();
+    };
+    // waits for a rewrite by `forget` to finish
+    journal.lock_shared().unwrap();
+    let mut contents = String::new();
+    journal.read_to_string(&mut contents).unwrap();
+    contents
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .filter_map(|(index, line)| match serde_json::from_str(line) {
+            Ok(entry) => Some(entry),
+            Err(error) => {
+                outln!(
+                    "WARNING: Skipped line {} of the journal, it is not a valid entry: {}",
+                    index + 1,
+                    error
+                );
+                None
+            }
+        })
+        .collect()
+}
+
+/// Drops an undone entry from the journal. Only the newest line holding it is dropped, so the
+/// entries other mute runs recorded since the journal was read are kept.
+fn forget(entry: &JournalEntry) {
+    let mut journal = locked_journal(&journal_path().unwrap());
+    let mut contents = String::new();
+    journal.read_to_string(&mut contents).unwrap();
+    let mut lines: Vec<&str> = contents.lines().collect();
+    let undone = lines.iter().rposition(|line| {
+        serde_json::from_str::<JournalEntry>(line).is_ok_and(|recorded| recorded == *entry)
+    });
+    if let Some(undone) = undone {
+        lines.remove(undone);
+    }
+    let kept: String = lines.iter().map(|line| format!("{}\n", line)).collect();
+    journal.set_len(0).unwrap();
+    journal.write_all(kept.as_bytes()).unwrap();
+}
+
+/// Points the journal at a directory of its own for a test, and keeps other tests using the journal
+/// waiting until the returned guard is dropped. The journal starts out empty.
+#[cfg(test)]
+pub fn isolated_journal() -> std::sync::MutexGuard<'static, ()> {
+    use std::sync::{Mutex, OnceLock};
+    static STATE_HOME: OnceLock<tempfile::TempDir> = OnceLock::new();
+    static JOURNAL: Mutex<()> = Mutex::new(());
+
+    let guard = JOURNAL.lock().unwrap_or_else(|error| error.into_inner());
+    let state_home = STATE_HOME.get_or_init(|| tempfile::tempdir().unwrap());
+    env::set_var("XDG_STATE_HOME", state_home.path());
+    fs::create_dir_all(journal_path().unwrap().parent().unwrap()).unwrap();
+    fs::write(journal_path().unwrap(), "").unwrap();
+    guard
+}
+
+/// Reverts the last `count` changes in the journal, newest first. The files are read and written
+/// like any other mutation, locked and keeping their symlinks and modification time as asked, but
+/// undoing isn't journaled itself. A dry-run or a check only reports what reverting would change,
+/// and leaves both the files and the journal alone.
+pub fn undo(count: usize, force: bool, options: &WriteOptions) {
+    let options = WriteOptions {
+        journal: false,
+        ..options.clone()
+    };
+    let mut entries = read_journal();
+    if count > entries.len() {
+        outln!(
+            "WARNING: Only {} changes are recorded in the journal. Please check `mute history` and try again.",
+            entries.len()
+        );
+        output::exit(exitcode::DATAERR);
+    }
+
+    if !is_writing(&options) {
+        return report_undo(&entries[entries.len() - count..], force, &options);
+    }
+    for _ in 0..count {
+        let entry = entries.last().unwrap();
+        let (contents, snapshot) = read_file(&entry.file, &options);
+        let reverted = revert(entry, &contents, force).unwrap_or_else(|warning| {
+            outln!("WARNING: {}", warning);
+            output::exit(exitcode::DATAERR);
+        });
+        write_contents(
+            entry.file.clone(),
+            &contents,
+            snapshot,
+            &reverted,
+            &entry.reverse,
+            &options,
+        );
+        outln!(
+            "Reverted {} change(s) to {}",
+            entry.reverse.len(),
+            entry.file
+        );
+
+        forget(entry);
+        entries.pop();
+    }
+}
+
+/// Reports what undoing the entries would change, newest first, for a dry-run or a check. An entry
+/// is reverted against what the newer entries of the same file would have left behind.
+fn report_undo(entries: &[JournalEntry], force: bool, options: &WriteOptions) {
+    let mut reverted_files: HashMap<&str, String> = HashMap::new();
+    let mut would_change = false;
+    for entry in entries.iter().rev() {
+        let contents = match reverted_files.remove(entry.file.as_str()) {
+            Some(contents) => contents,
+            None => read_file(&entry.file, options).0,
+        };
+        let reverted = revert(entry, &contents, force).unwrap_or_else(|warning| {
+            outln!("WARNING: {}", warning);
+            output::exit(exitcode::DATAERR);
+        });
+        if !options.check || reverted != contents {
+            outln!("(File: {})", entry.file);
+            print_changes(&contents, &entry.reverse, &options.display);
+            print_hashes(&contents, &reverted);
+            would_change |= reverted != contents;
+        }
+        reverted_files.insert(&entry.file, reverted);
+    }
+    if options.check && would_change {
+        output::exit(WOULD_CHANGE);
+    }
+}
+
+/// Works out what the file of the entry looked like before the change from its current contents.
+/// Refuses when the file changed since, unless forced, and when reverting doesn't give back the
+/// contents the journal says the file had, which means the entry doesn't describe the change.
+fn revert(entry: &JournalEntry, contents: &str, force: bool) -> Result<String, String> {
+    if content_hash(contents) != entry.after && !force {
+        return Err(format!(
+            "{} has changed since mute changed it. Use --force to undo anyway.",
+            entry.file
+        ));
+    }
+    let total_lines = contents.split('\n').count();
+    if entry
+        .reverse
+        .iter()
+        .any(|change| change.line + change.removed.len() > total_lines)
+    {
+        return Err(format!("{} is too short to revert the change.", entry.file));
+    }
+
+    match restore(contents, &entry.reverse, &entry.before) {
+        Some(reverted) => Ok(reverted),
+        None if force => Ok(apply_changes(contents, &entry.reverse)),
+        None => Err(format!(
+            "Reverting the change to {} doesn't give back the contents with the hash {} it had before. Nothing was changed.",
+            entry.file, entry.before
+        )),
+    }
+}
+
+/// The contents the reverse changes turn `contents` back into, as long as they have the hash
+/// `before` the original had.
+pub fn restore(contents: &str, reverse: &[Change], before: &str) -> Option<String> {
+    let reverted = apply_changes(contents, reverse);
+    if content_hash(&reverted) == before {
+        return Some(reverted);
+    }
+    // lines can't tell whether the original ended with a new line, but its hash can
+    let toggled = match reverted.strip_suffix('\n') {
+        Some(stripped) => stripped.to_owned(),
+        None => format!("{}\n", reverted),
+    };
+    (content_hash(&toggled) == before).then_some(toggled)
+}
+
+pub fn history() {
+    for (index, entry) in read_journal().iter().rev().enumerate() {
+        outln!("{}", history_line(index + 1, entry));
+    }
+}
+
+fn history_line(number: usize, entry: &JournalEntry) -> String {
+    format!(
+        "{}\t{}\t{}\t{} -> {}\t({} change(s))",
+        number,
+        format_time(entry.time),
+        entry.file,
+        short_hash(&entry.before),
+        short_hash(&entry.after),
+        entry.reverse.len()
+    )
+}
+
+fn short_hash(hash: &str) -> &str {
+    let digest = hash.strip_prefix("sha256:").unwrap_or(hash);
+    &digest[..digest.len().min(12)]
+}
+
+/// Formats seconds since the unix epoch as a UTC `YYYY-MM-DD HH:MM:SS` timestamp.
+fn format_time(time: u64) -> String {
+    let (days, seconds) = (time / 86_400, time % 86_400);
+    // days to a civil date, see http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+    let z = days as i64 + 719_468;
+    let era = z.div_euclid(146_097);
+    let day_of_era = z.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1_460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+    let month = if shifted_month < 10 {
+        shifted_month + 3
+    } else {
+        shifted_month - 9
+    };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}:{:02}",
+        year,
+        month,
+        day,
+        seconds / 3_600,
+        seconds % 3_600 / 60,
+        seconds % 60
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, io::Write};
+    use tempfile::NamedTempFile;
+
+    use crate::cli::parser::{ColorWhen, DisplayOptions, WriteOptions};
+    use crate::cmd::add::add_via_line_number;
+    use crate::cmd::change::{apply_changes, Change};
+    use crate::cmd::journal::{
+        forget, format_time, history_line, isolated_journal, read_journal, record, reverse_changes,
+        revert, undo, JournalEntry,
+    };
+    use crate::cmd::matcher::LineSpan;
+    use crate::cmd::output::capture;
+    use crate::cmd::remove::remove_via_line_number;
+    use crate::cmd::WOULD_CHANGE;
+
+    #[test]
+    fn test_reverse_changes() {
+        let contents = "a\nb\nc\nd";
+        let lines: Vec<&str> = contents.split('\n').collect();
+        let changes = vec![
+            Change::insert(0, "start"),
+            Change::replace(&lines, LineSpan { start: 1, end: 2 }, "bc"),
+            Change::remove(&lines, LineSpan { start: 3, end: 3 }),
+        ];
+        let mutated = apply_changes(contents, &changes);
+        let reverted = apply_changes(&mutated, &reverse_changes(&changes));
+        // the new line left behind by removing the last line is fixed up by `undo`
+        assert_eq!("a\nb\nc\nd\n", reverted);
+    }
+
+    #[test]
+    fn test_undo_and_history() {
+        let _journal = isolated_journal();
+        // create test file
+        let mut file1 = NamedTempFile::new().unwrap();
+        file1.write_all("a\nb\nc\n".as_bytes()).unwrap();
+        file1.flush().unwrap();
+        let file_path = file1.path().to_str().unwrap().to_owned();
+        // mutate file twice
+        let options = WriteOptions {
+            journal: true,
+            ..Default::default()
+        };
+        add_via_line_number(file_path.clone(), 1, "first".to_owned(), &options);
+        remove_via_line_number(file_path.clone(), -1, &options);
+        assert_eq!("first\na\nb\n", fs::read_to_string(&file_path).unwrap());
+        // the history lists the newest change first
+        let entries = read_journal();
+        assert_eq!(2, entries.len());
+        assert!(history_line(1, &entries[1]).contains(&format!("\t{}\t", entries[1].file)));
+        assert!(history_line(1, &entries[1]).ends_with("(1 change(s))"));
+        // undo the last change
+        undo(1, false, &options);
+        assert_eq!("first\na\nb\nc\n", fs::read_to_string(&file_path).unwrap());
+        assert_eq!(1, read_journal().len());
+        // undo refuses a file changed since, unless forced
+        let entry: JournalEntry = read_journal().pop().unwrap();
+        assert!(revert(&entry, "first\na\nb\nc\nd\n", false).is_err());
+        assert_eq!(
+            Ok("a\nb\nc\nd\n".to_owned()),
+            revert(&entry, "first\na\nb\nc\nd\n", true)
+        );
+        // and an entry that doesn't turn the file back into what it was
+        let wrong = JournalEntry {
+            reverse: Vec::new(),
+            ..entry.clone()
+        };
+        assert!(revert(&wrong, "first\na\nb\nc\n", false).is_err());
+        // undo the first change
+        undo(1, false, &options);
+        assert_eq!("a\nb\nc\n", fs::read_to_string(&file_path).unwrap());
+        assert!(read_journal().is_empty());
+    }
+
+    #[test]
+    fn test_undo_dry_run_and_check() {
+        let _journal = isolated_journal();
+        // create test file
+        let mut file1 = NamedTempFile::new().unwrap();
+        file1.write_all("a\nb\n".as_bytes()).unwrap();
+        file1.flush().unwrap();
+        let file_path = file1.path().to_str().unwrap().to_owned();
+        // mutate file twice
+        let options = WriteOptions {
+            journal: true,
+            ..Default::default()
+        };
+        add_via_line_number(file_path.clone(), 1, "first".to_owned(), &options);
+        add_via_line_number(file_path.clone(), 1, "second".to_owned(), &options);
+        // undo both changes as a dry-run, the older one against what the newer one leaves behind
+        let dry_run = WriteOptions {
+            dry_run: true,
+            display: DisplayOptions {
+                color: ColorWhen::Never,
+                ..Default::default()
+            },
+            ..options.clone()
+        };
+        let outcome = capture(true, || undo(2, false, &dry_run));
+        assert_eq!(exitcode::OK, outcome.code);
+        assert!(outcome.stdout.contains("(Line: 1)\t --- second\n"));
+        assert!(outcome.stdout.contains("(Line: 1)\t --- first\n"));
+        // check the undo, which would change the file
+        let check = WriteOptions {
+            dry_run: false,
+            check: true,
+            ..dry_run
+        };
+        let outcome = capture(true, || undo(1, false, &check));
+        assert_eq!(WOULD_CHANGE, outcome.code);
+        // neither touched the file or the journal
+        assert_eq!(
+            "second\nfirst\na\nb\n",
+            fs::read_to_string(&file_path).unwrap()
+        );
+        assert_eq!(2, read_journal().len());
+    }
+
+    #[test]
+    fn test_forget_keeps_newer_entries() {
+        let _journal = isolated_journal();
+        let record_after =
+            |after: &str| record("app.toml", "sha256:0".to_owned(), after.to_owned(), &[]);
+        // record entries, reading the journal the way undo does
+        record_after("sha256:1");
+        record_after("sha256:2");
+        let read = read_journal();
+        // another run records an entry while the last one read is being undone
+        record_after("sha256:3");
+        forget(&read[1]);
+        // compare results
+        let afters: Vec<String> = read_journal()
+            .into_iter()
+            .map(|entry| entry.after)
+            .collect();
+        assert_eq!(vec!["sha256:1", "sha256:3"], afters);
+    }
+
+    #[test]
+    fn test_format_time() {
+        assert_eq!("1970-01-01 00:00:00", format_time(0));
+        assert_eq!("2024-02-29 12:34:56", format_time(1_709_210_096));
+    }
+}
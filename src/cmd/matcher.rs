@@ -0,0 +1,356 @@
+use super::{
+    comment::CommentSyntax,
+    line_count,
+    output::{self, outln},
+    UNEXPECTED_MATCH_COUNT,
+};
+use crate::cli::parser::{ExpectedMatches, MatchOptions, PatternMutation};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+
+/// An inclusive range of 0-indexed lines.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LineSpan {
+    pub start: usize,
+    pub end: usize,
+}
+
+/// Compiles a pattern given on the command line, exiting with a warning when it isn't a valid
+/// regex.
+pub fn compile_pattern(pattern: &str) -> Regex {
+    Regex::new(pattern).unwrap_or_else(|error| {
+        // the error points at the problem over several lines, its last line says what it is
+        let error = error.to_string();
+        let reason = error.lines().last().unwrap_or_default();
+        outln!(
+            "WARNING: `{}` is not a valid regex: {}",
+            pattern,
+            reason.trim_start_matches("error: ")
+        );
+        output::exit(exitcode::DATAERR);
+    })
+}
+
+/// Decides which lines of a file a pattern refers to.
+pub struct LineMatcher {
+    regex: Regex,
+    multiline: bool,
+    invert: bool,
+    comments: Vec<CommentSyntax>,
+    expected: ExpectedMatches,
+}
+
+impl LineMatcher {
+    pub fn new(pattern: &str, options: &MatchOptions) -> LineMatcher {
+        let mut pattern = if options.fixed_strings {
+            regex::escape(pattern)
+        } else {
+            pattern.to_owned()
+        };
+        // like `grep -w`, the match can't have a word character right before or after it, which
+        // unlike `\b` also works for patterns starting or ending with a symbol, e.g. `[[security]]`
+        if options.word {
+            pattern = format!(r"\b{{start-half}}(?:{})\b{{end-half}}", pattern);
+        }
+        if options.line {
+            pattern = format!("^(?:{})$", pattern);
+        }
+
+        // the flags are part of the pattern, so a `RegexSet` built from patterns matches the same way
+        let flags = match (options.ignore_case, options.multiline) {
+            (true, true) => "(?ims)",
+            (true, false) => "(?i)",
+            (false, true) => "(?ms)",
+            (false, false) => "",
+        };
+        let regex = compile_pattern(&format!("{}{}", flags, pattern));
+        LineMatcher {
+            regex,
+            multiline: options.multiline,
+            invert: options.invert_match,
+            comments: Vec::new(),
+            expected: ExpectedMatches::default(),
+        }
+    }
+
+    /// Builds the matcher for a pattern subcommand run against `file_path`, whose extension decides
+    /// the comment syntax when `--ignore-comments` doesn't name one.
+    pub fn for_file(pattern_mut: &PatternMutation, file_path: &str) -> LineMatcher {
+        let matcher = LineMatcher {
+            expected: pattern_mut.expected,
+            ..LineMatcher::new(&pattern_mut.pattern, &pattern_mut.options)
+        };
+        match &pattern_mut.ignore_comments {
+            Some(marker) => {
+                matcher.ignoring_comments(CommentSyntax::resolve(marker.as_deref(), file_path))
+            }
+            None => matcher,
+        }
+    }
+
+    /// Comment lines written in any of the syntaxes are never matched, even when inverted.
+    pub fn ignoring_comments(self, comments: Vec<CommentSyntax>) -> LineMatcher {
+        LineMatcher { comments, ..self }
+    }
+
+    pub fn is_match(&self, line: &str) -> bool {
+        !self.is_comment(line) && self.regex.is_match(line) != self.invert
+    }
+
+    fn is_comment(&self, line: &str) -> bool {
+        self.comments.iter().any(|syntax| syntax.is_comment(line))
+    }
+
+    /// The regex with all of its flags.
+    pub fn pattern(&self) -> &str {
+        self.regex.as_str()
+    }
+
+    /// The regex, when what it matched on a line is what the matcher selected. Inverted and
+    /// multiline matchers select lines another way.
+    pub fn line_pattern(&self) -> Option<&str> {
+        (!self.invert && !self.multiline).then(|| self.pattern())
+    }
+
+    pub fn allows_no_match(&self) -> bool {
+        self.expected.allows_no_match()
+    }
+
+    pub fn is_multiline(&self) -> bool {
+        self.multiline
+    }
+
+    /// The lines matched in the file, in order. Each line is its own span unless the matcher is
+    /// multiline, in which case a span covers every line a match touches. An inverted matcher
+    /// returns every line outside of those spans, one span per line.
+    ///
+    /// Exits when the number of spans isn't the number of matches the user expected.
+    pub fn find_spans(&self, contents: &str) -> Vec<LineSpan> {
+        if !self.multiline {
+            let matched: Vec<bool> = contents
+                .split('\n')
+                .map(|line| self.regex.is_match(line))
+                .collect();
+            return self.spans_from_lines(contents, &matched);
+        }
+        self.select_spans(contents, self.find_multiline_spans(contents))
+    }
+
+    /// Like `find_spans`, for when it's already known which lines of the file the regex matches,
+    /// so several matchers can share a single pass over the file. Not for multiline matchers.
+    pub fn spans_from_lines(&self, contents: &str, matched: &[bool]) -> Vec<LineSpan> {
+        let spans = contents
+            .split('\n')
+            .zip(matched)
+            .enumerate()
+            .filter(|(_, (line, &matched))| matched && !self.is_comment(line))
+            .map(|(index, _)| LineSpan {
+                start: index,
+                end: index,
+            })
+            .collect();
+        self.select_spans(contents, spans)
+    }
+
+    /// Turns the spans the regex matched into the ones the matcher selects, checking their number.
+    fn select_spans(&self, contents: &str, spans: Vec<LineSpan>) -> Vec<LineSpan> {
+        let spans = if self.invert {
+            self.invert_spans(contents, &spans)
+        } else {
+            spans
+        };
+        if let Some(expected) = unexpected_count(&self.expected, spans.len()) {
+            outln!(
+                "WARNING: The pattern was expected to match {} time(s) but matched {}. The file was not changed.",
+                expected,
+                spans.len()
+            );
+            output::exit(UNEXPECTED_MATCH_COUNT);
+        }
+        spans
+    }
+
+    fn invert_spans(&self, contents: &str, spans: &[LineSpan]) -> Vec<LineSpan> {
+        // the empty string after a trailing new line isn't a line, so it can't fail to match either
+        let lines: Vec<&str> = contents.split('\n').collect();
+        let mut matched = spans.iter().peekable();
+        (0..line_count(contents))
+            .filter(|&index| {
+                while matched.next_if(|span| span.end < index).is_some() {}
+                matched.peek().is_none_or(|span| index < span.start)
+                    && !self.is_comment(lines[index])
+            })
+            .map(|index| LineSpan {
+                start: index,
+                end: index,
+            })
+            .collect()
+    }
+
+    fn find_multiline_spans(&self, contents: &str) -> Vec<LineSpan> {
+        if self.comments.is_empty() {
+            return self.find_regex_spans(contents);
+        }
+
+        // blank out comments so a match can't use their text, then drop matches that still
+        // reach across a (now empty) comment line
+        let lines: Vec<&str> = contents.split('\n').collect();
+        let blanked = lines
+            .iter()
+            .map(|&line| if self.is_comment(line) { "" } else { line })
+            .collect::<Vec<&str>>()
+            .join("\n");
+        self.find_regex_spans(&blanked)
+            .into_iter()
+            .filter(|span| {
+                !lines[span.start..=span.end]
+                    .iter()
+                    .any(|line| self.is_comment(line))
+            })
+            .collect()
+    }
+
+    fn find_regex_spans(&self, contents: &str) -> Vec<LineSpan> {
+        let mut spans: Vec<LineSpan> = Vec::new();
+        let (mut position, mut line) = (0, 0);
+        for found in self.regex.find_iter(contents) {
+            line += contents[position..found.start()].matches('\n').count();
+            position = found.start();
+            let mut end = line + found.as_str().matches('\n').count();
+            // a match ending on a new line doesn't reach into the next line
+            if found.as_str().ends_with('\n') {
+                end -= 1;
+            }
+            match spans.last_mut() {
+                Some(last) if line <= last.end => last.end = last.end.max(end),
+                _ => spans.push(LineSpan { start: line, end }),
+            }
+        }
+        spans
+    }
+}
+
+/// Describes the expectation a number of matches breaks, e.g. `at most 1`.
+fn unexpected_count(expected: &ExpectedMatches, count: usize) -> Option<String> {
+    [
+        ("exactly", expected.expect_count.filter(|&n| count != n)),
+        ("at least", expected.expect_at_least.filter(|&n| count < n)),
+        ("at most", expected.expect_at_most.filter(|&n| count > n)),
+    ]
+    .into_iter()
+    .find_map(|(bound, n)| n.map(|n| format!("{} {}", bound, n)))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::cli::parser::{ExpectedMatches, MatchOptions};
+    use crate::cmd::comment::CommentSyntax;
+    use crate::cmd::matcher::{unexpected_count, LineMatcher, LineSpan};
+
+    #[test]
+    fn test_fixed_strings() {
+        let options = MatchOptions {
+            fixed_strings: true,
+            ..Default::default()
+        };
+        let matcher = LineMatcher::new("[[security]]", &options);
+        assert!(matcher.is_match("[[security]]"));
+        assert!(!matcher.is_match("[security]"));
+    }
+
+    #[test]
+    fn test_ignore_case_word() {
+        let options = MatchOptions {
+            ignore_case: true,
+            word: true,
+            ..Default::default()
+        };
+        let matcher = LineMatcher::new("entry", &options);
+        assert!(matcher.is_match("ENTRY = 1"));
+        assert!(!matcher.is_match("entry_1 = 1"));
+    }
+
+    #[test]
+    fn test_word_with_symbols() {
+        let options = MatchOptions {
+            fixed_strings: true,
+            word: true,
+            ..Default::default()
+        };
+        let matcher = LineMatcher::new("[[security]]", &options);
+        assert!(matcher.is_match("[[security]]"));
+        assert!(matcher.is_match("# [[security]] table"));
+        assert!(!matcher.is_match("x[[security]]"));
+        assert!(!matcher.is_match("[[security]]_1"));
+    }
+
+    #[test]
+    fn test_whole_line() {
+        let options = MatchOptions {
+            line: true,
+            ..Default::default()
+        };
+        let matcher = LineMatcher::new("a|b", &options);
+        assert!(matcher.is_match("b"));
+        assert!(!matcher.is_match("ab"));
+    }
+
+    #[test]
+    fn test_multiline_spans() {
+        let options = MatchOptions {
+            multiline: true,
+            ..Default::default()
+        };
+        let matcher = LineMatcher::new(r"^\[\[security]]\nentry_2=2", &options);
+        let contents = "[[security]]\nentry_1=1\n[[security]]\nentry_2=2\nentry_3=3";
+        assert_eq!(
+            vec![LineSpan { start: 2, end: 3 }],
+            matcher.find_spans(contents)
+        );
+    }
+
+    #[test]
+    fn test_inverted_multiline_spans() {
+        let options = MatchOptions {
+            multiline: true,
+            invert_match: true,
+            ..Default::default()
+        };
+        let matcher = LineMatcher::new(r"^b\nc$", &options);
+        assert_eq!(
+            vec![LineSpan { start: 0, end: 0 }, LineSpan { start: 3, end: 3 }],
+            matcher.find_spans("a\nb\nc\nd\n")
+        );
+    }
+
+    #[test]
+    fn test_ignore_comments() {
+        let matcher = LineMatcher::new(r"^\s*#?\s*entry_2", &MatchOptions::default())
+            .ignoring_comments(vec![CommentSyntax::new("#")]);
+        let contents = "# entry_2=\"two\"\nentry_2=\"two\"";
+        assert_eq!(
+            vec![LineSpan { start: 1, end: 1 }],
+            matcher.find_spans(contents)
+        );
+    }
+
+    #[test]
+    fn test_unexpected_count() {
+        let exactly = ExpectedMatches {
+            expect_count: Some(1),
+            ..Default::default()
+        };
+        assert_eq!(None, unexpected_count(&exactly, 1));
+        assert_eq!(Some("exactly 1".to_owned()), unexpected_count(&exactly, 7));
+
+        let between = ExpectedMatches {
+            expect_at_least: Some(2),
+            expect_at_most: Some(3),
+            ..Default::default()
+        };
+        assert_eq!(Some("at least 2".to_owned()), unexpected_count(&between, 1));
+        assert_eq!(None, unexpected_count(&between, 3));
+        assert_eq!(Some("at most 3".to_owned()), unexpected_count(&between, 4));
+        assert_eq!(None, unexpected_count(&ExpectedMatches::default(), 0));
+    }
+}
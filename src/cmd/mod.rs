@@ -1,7 +1,34 @@
-use std::fs::File;
+use sha2::{Digest, Sha256};
+use std::{
+    fs::{self, File, Metadata, TryLockError},
+    io::Write,
+    path::Path,
+    thread,
+    time::{Duration, Instant, SystemTime},
+};
+use tempfile::NamedTempFile;
+
+use crate::cli::parser::{DisplayOptions, LineRange, WhereCommands, WriteOptions};
+use change::{apply_changes, print_changes, Change};
+use interactive::confirm_changes;
+use matcher::{LineMatcher, LineSpan};
+use output::outln;
 
 const NEW_LINE: u8 = b'\n';
 
+/// Exit code for a pattern that matched more or fewer times than `--expect-*` allows.
+pub const UNEXPECTED_MATCH_COUNT: exitcode::ExitCode = 3;
+
+/// Exit code for `--check` when the file would change.
+pub const WOULD_CHANGE: exitcode::ExitCode = 1;
+
+/// Exit code for a file that doesn't have the hash `--if-hash` expects, or that changed while it
+/// was being mutated.
+pub const FILE_CHANGED: exitcode::ExitCode = 4;
+
+/// Exit code for `--git-clean` when the file has uncommitted changes.
+pub const DIRTY_FILE: exitcode::ExitCode = 5;
+
 fn file_from_dry_run(file_path: String, dry_run: bool) -> File {
     if dry_run {
         std::fs::OpenOptions::new()
@@ -17,5 +44,684 @@ fn file_from_dry_run(file_path: String, dry_run: bool) -> File {
     }
 }
 
+/// Number of lines in the file. A trailing new line does not start another line.
+fn line_count(contents: &str) -> usize {
+    if contents.is_empty() {
+        return 0;
+    }
+    contents
+        .strip_suffix('\n')
+        .unwrap_or(contents)
+        .split('\n')
+        .count()
+}
+
+/// Converts a 1-indexed line number into a 0-indexed line index. Negative line numbers count
+/// backwards from the end of the file, so `-1` is the last line. Returns `None` when a negative
+/// line number reaches past the start of the file.
+fn line_index(contents: &str, line_no: isize) -> Option<usize> {
+    assert_ne!(
+        line_no, 0,
+        "Line numbers are 1-indexed. Cannot have a line 0."
+    );
+    if line_no > 0 {
+        Some(line_no as usize - 1)
+    } else {
+        line_count(contents).checked_sub(line_no.unsigned_abs())
+    }
+}
+
+/// Like `line_index`, but exits when the line is not in the file.
+fn find_line(contents: &str, line_no: isize) -> usize {
+    match line_index(contents, line_no) {
+        Some(index) if index < contents.split('\n').count() => index,
+        _ => line_not_found(),
+    }
+}
+
+fn line_not_found() -> ! {
+    outln!("WARNING: Line was not found. Please check the file and try again.");
+    output::exit(exitcode::DATAERR);
+}
+
+/// The lines an addressing mode refers to when it isn't used to add an entry: the matched lines
+/// for `overwrite-pattern`, the line after or before each match for `after-pattern` and
+/// `before-pattern`, and the numbered line for `at-line` and `overwrite-line`.
+fn addressed_spans(file_path: &str, contents: &str, address: &WhereCommands) -> Vec<LineSpan> {
+    let total_lines = contents.split('\n').count();
+    let spans: Vec<LineSpan> = match address {
+        WhereCommands::AfterPattern(pattern_mut) => LineMatcher::for_file(pattern_mut, file_path)
+            .find_spans(contents)
+            .into_iter()
+            .filter(|span| span.end + 1 < total_lines)
+            .map(|span| LineSpan {
+                start: span.end + 1,
+                end: span.end + 1,
+            })
+            .collect(),
+        WhereCommands::BeforePattern(pattern_mut) => LineMatcher::for_file(pattern_mut, file_path)
+            .find_spans(contents)
+            .into_iter()
+            .filter(|span| span.start > 0)
+            .map(|span| LineSpan {
+                start: span.start - 1,
+                end: span.start - 1,
+            })
+            .collect(),
+        WhereCommands::OverwritePattern(pattern_mut) => {
+            LineMatcher::for_file(pattern_mut, file_path).find_spans(contents)
+        }
+        WhereCommands::AtLine(line_mut) | WhereCommands::OverwriteLine(line_mut) => {
+            let index = find_line(contents, line_mut.line_number);
+            vec![LineSpan {
+                start: index,
+                end: index,
+            }]
+        }
+    };
+
+    let allows_no_match = address
+        .pattern()
+        .is_some_and(|pattern_mut| pattern_mut.expected.allows_no_match());
+    if spans.is_empty() && !allows_no_match {
+        pattern_not_found();
+    }
+    spans
+}
+
+/// Like `find_line`, but for both ends of a range, which has to end on a line of the file rather
+/// than on the nothing after its final new line. Exits when the range is backwards.
+fn find_range(contents: &str, range: LineRange) -> LineSpan {
+    let (start, end) = (
+        find_line(contents, range.start),
+        find_line(contents, range.end),
+    );
+    if end >= line_count(contents) {
+        line_not_found();
+    }
+    if start > end {
+        outln!("WARNING: The range ends before it starts. Please check the line numbers and try again.");
+        output::exit(exitcode::DATAERR);
+    }
+    LineSpan { start, end }
+}
+
+fn pattern_not_found() -> ! {
+    outln!("WARNING: Pattern was not found. Please check the file, the regex and try again.");
+    output::exit(exitcode::DATAERR);
+}
+
+/// What a file looked like when it was read. Comparing it with a fresh look right before writing
+/// catches another process changing the file in the meantime.
+#[derive(Debug, Default)]
+struct Snapshot {
+    len: u64,
+    modified: Option<SystemTime>,
+    inode: u64,
+    /// The lock on the file, held until the snapshot is dropped after writing.
+    lock: Option<File>,
+    /// Whether the file is missing, to be created by `--create` when it's written.
+    missing: bool,
+}
+
+impl Snapshot {
+    fn of(metadata: &Metadata) -> Snapshot {
+        #[cfg(unix)]
+        let inode = std::os::unix::fs::MetadataExt::ino(metadata);
+        #[cfg(not(unix))]
+        let inode = 0;
+        Snapshot {
+            len: metadata.len(),
+            modified: metadata.modified().ok(),
+            inode,
+            lock: None,
+            missing: false,
+        }
+    }
+
+    /// Takes the lock on the file for writing it, unless it was already taken when the file was
+    /// read. Interactive runs only take it once the changes have been chosen, so the file isn't
+    /// locked while they wait for answers, and `verify` catches anything changing it meanwhile.
+    fn locked(self, file_path: &str, options: &WriteOptions) -> Snapshot {
+        if self.lock.is_some() || !options.lock {
+            return self;
+        }
+        Snapshot {
+            lock: Some(lock_file(file_path, options.lock_timeout)),
+            ..self
+        }
+    }
+
+    fn looks_like(&self, other: &Snapshot) -> bool {
+        (self.len, self.modified, self.inode) == (other.len, other.modified, other.inode)
+    }
+
+    /// Exits when the file at `file_path` no longer looks like the snapshot.
+    fn verify(&self, file_path: &str) {
+        let unchanged =
+            fs::metadata(file_path).is_ok_and(|metadata| self.looks_like(&Snapshot::of(&metadata)));
+        if !unchanged {
+            outln!("WARNING: {} was changed by someone else while it was being mutated. Nothing was written, please try again.", file_path);
+            output::exit(FILE_CHANGED);
+        }
+    }
+}
+
+/// Whether the file is going to be written, rather than only having the changes reported for a
+/// dry-run or a check or written to a patch or a plan.
+fn is_writing(options: &WriteOptions) -> bool {
+    !options.dry_run && !options.check && options.emit_patch.is_none() && options.plan.is_none()
+}
+
+/// Reads the file to mutate, exiting when it doesn't have the hash `--if-hash` expects. Unless
+/// nothing is going to be written or the run is interactive, the file stays locked for as long as
+/// the snapshot lives.
+fn read_file(file_path: &str, options: &WriteOptions) -> (String, Snapshot) {
+    let writing = is_writing(options);
+    if writing && options.git_clean {
+        git::ensure_clean(file_path);
+    }
+    if !file_exists(file_path, options) {
+        check_hash(file_path, &content_hash(""), options);
+        let snapshot = Snapshot {
+            missing: true,
+            ..Default::default()
+        };
+        return (String::new(), snapshot);
+    }
+    let snapshot = take_snapshot(file_path, options);
+    let contents = fs::read_to_string(file_path).unwrap();
+    check_hash(file_path, &content_hash(&contents), options);
+    (contents, snapshot)
+}
+
+/// A snapshot of the file that is about to be read, holding the lock on it unless nothing is going
+/// to be written or the run is interactive. Looking before reading means a write racing the read
+/// shows up as a changed snapshot.
+fn take_snapshot(file_path: &str, options: &WriteOptions) -> Snapshot {
+    let lock = (is_writing(options) && options.lock && !options.interactive)
+        .then(|| lock_file(file_path, options.lock_timeout));
+    Snapshot {
+        lock,
+        ..Snapshot::of(&fs::metadata(file_path).unwrap())
+    }
+}
+
+/// Whether the file exists. Exits when it doesn't, unless `--create` was given, in which case the
+/// file is treated as empty and only created once the mutation is written.
+fn file_exists(file_path: &str, options: &WriteOptions) -> bool {
+    if fs::metadata(file_path).is_ok() {
+        return true;
+    }
+    if !options.create {
+        outln!(
+            "WARNING: {} does not exist. Please check the path, or use --create to start from an empty file.",
+            file_path
+        );
+        output::exit(exitcode::NOINPUT);
+    }
+    false
+}
+
+/// Creates the missing file for `--create`, with its parent directories for `--parents`, right
+/// before it's written. Exits when someone else created it since it was found missing.
+fn create_file(file_path: &str, options: &WriteOptions) {
+    let parent = Path::new(file_path)
+        .parent()
+        .filter(|parent| !parent.as_os_str().is_empty());
+    match parent {
+        Some(parent) if options.parents => fs::create_dir_all(parent).unwrap(),
+        Some(parent) if !parent.is_dir() => {
+            outln!(
+                "WARNING: The directory {} does not exist. Use --parents to create it too.",
+                parent.display()
+            );
+            output::exit(exitcode::NOINPUT);
+        }
+        _ => {}
+    }
+    let Ok(file) = File::create_new(file_path) else {
+        outln!("WARNING: {} was created by someone else while it was being mutated. Nothing was written, please try again.", file_path);
+        output::exit(FILE_CHANGED);
+    };
+    #[cfg(unix)]
+    if let Some(mode) = options.mode {
+        use std::os::unix::fs::PermissionsExt;
+        file.set_permissions(fs::Permissions::from_mode(mode))
+            .unwrap();
+    }
+}
+
+/// Swaps a symlink for a regular file with the contents, permissions and (where allowed) owner of
+/// the file it points to, so mutating it leaves the link target alone. Only done right before
+/// writing, while the file is locked, so a mutation that fails leaves the link as it was.
+fn replace_symlink(file_path: &str) {
+    let is_symlink =
+        fs::symlink_metadata(file_path).is_ok_and(|metadata| metadata.file_type().is_symlink());
+    if !is_symlink {
+        return;
+    }
+    // the copy gets a fresh name next to the link, so it never clobbers a file that's already there
+    let directory = Path::new(file_path)
+        .parent()
+        .filter(|parent| !parent.as_os_str().is_empty())
+        .unwrap_or(Path::new("."));
+    let copy = NamedTempFile::new_in(directory).unwrap();
+    // copying follows the link and keeps the permissions, renaming swaps the link out in one go
+    fs::copy(file_path, copy.path()).unwrap();
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::MetadataExt;
+        let target = fs::metadata(file_path).unwrap();
+        // only root can give a file away, everyone else keeps their own copy
+        let _ = std::os::unix::fs::chown(copy.path(), Some(target.uid()), Some(target.gid()));
+    }
+    copy.persist(file_path).unwrap();
+}
+
+/// Puts the modification time from before the mutation back for `--preserve-mtime`.
+fn restore_mtime(file: &File, snapshot: &Snapshot, options: &WriteOptions) {
+    if let (true, Some(modified)) = (options.preserve_mtime, snapshot.modified) {
+        file.set_modified(modified).unwrap();
+    }
+}
+
+/// Takes an exclusive advisory lock on the file, waiting up to `timeout` seconds for another
+/// process to release it. The lock is released when the returned file is dropped.
+fn lock_file(file_path: &str, timeout: u64) -> File {
+    let file = File::open(file_path).unwrap();
+    let deadline = Instant::now() + Duration::from_secs(timeout);
+    loop {
+        match file.try_lock() {
+            Ok(()) => return file,
+            Err(TryLockError::WouldBlock) if Instant::now() < deadline => {
+                thread::sleep(Duration::from_millis(50))
+            }
+            Err(TryLockError::WouldBlock) => {
+                outln!(
+                    "WARNING: {} is still locked by another process after waiting {} second(s). Nothing was changed.",
+                    file_path, timeout
+                );
+                output::exit(exitcode::TEMPFAIL);
+            }
+            Err(TryLockError::Error(error)) => panic!("Could not lock {}: {}", file_path, error),
+        }
+    }
+}
+
+/// Exits when `--if-hash` was given and the file's current hash is a different one.
+fn check_hash(file_path: &str, hash: &str, options: &WriteOptions) {
+    match &options.if_hash {
+        Some(expected) if !expected.eq_ignore_ascii_case(hash) => {
+            outln!(
+                "WARNING: {} has the hash {} instead of {}. Nothing was changed.",
+                file_path,
+                hash,
+                expected
+            );
+            output::exit(FILE_CHANGED);
+        }
+        _ => {}
+    }
+}
+
+/// Writes the changes to the file, or only reports them for a dry-run or a check, or writes them
+/// to a patch or a plan.
+fn commit_changes(
+    file_path: String,
+    contents: &str,
+    snapshot: Snapshot,
+    changes: &[Change],
+    options: &WriteOptions,
+) {
+    let accepted;
+    let changes = if options.interactive {
+        accepted = confirm_changes(contents, changes, &options.display);
+        if accepted.is_empty() {
+            return;
+        }
+        &accepted
+    } else {
+        changes
+    };
+    let mutated = apply_changes(contents, changes);
+    if options.check {
+        check_changes(contents, &mutated, changes, &options.display);
+    }
+    if is_writing(options) {
+        write_contents(file_path, contents, snapshot, &mutated, changes, options);
+    } else {
+        report_changes(&file_path, contents, &mutated, changes, options);
+    }
+}
+
+/// Reports the changes without making them: as a unified diff for `--emit-patch`, in a plan for
+/// `mute plan`, and the way a dry-run shows them otherwise.
+fn report_changes(
+    file_path: &str,
+    contents: &str,
+    mutated: &str,
+    changes: &[Change],
+    options: &WriteOptions,
+) {
+    if let Some(patch_path) = &options.emit_patch {
+        patch::emit_patch(patch_path, file_path, contents, mutated);
+    } else if let Some(plan_file) = &options.plan {
+        plan::write_plan(plan_file, file_path, contents, mutated, changes);
+    } else {
+        print_changes(contents, changes, &options.display);
+        print_hashes(contents, mutated);
+    }
+}
+
+/// Commits the changes to several files read up front, like the ones a patch or a plan names. A
+/// check reports every file that would change before exiting.
+fn commit_files(files: Vec<(String, String, Snapshot, Vec<Change>)>, options: &WriteOptions) {
+    if options.check {
+        let mut would_change = false;
+        for (file_path, contents, _, changes) in &files {
+            let mutated = apply_changes(contents, changes);
+            if mutated != *contents {
+                outln!("(File: {})", file_path);
+                print_changes(contents, changes, &options.display);
+                print_hashes(contents, &mutated);
+                would_change = true;
+            }
+        }
+        output::exit(if would_change {
+            WOULD_CHANGE
+        } else {
+            exitcode::OK
+        });
+    }
+    for (file_path, contents, snapshot, changes) in files {
+        if !is_writing(options) || options.interactive {
+            outln!("(File: {})", file_path);
+        }
+        commit_changes(file_path, &contents, snapshot, &changes, options);
+    }
+}
+
+/// Exits successfully when the changes leave the file as it is, otherwise reports them and exits
+/// with `WOULD_CHANGE`.
+fn check_changes(contents: &str, mutated: &str, changes: &[Change], display: &DisplayOptions) -> ! {
+    let changes: Vec<Change> = changes
+        .iter()
+        .filter(|change| change.removed != change.added)
+        .cloned()
+        .collect();
+    if changes.is_empty() {
+        output::exit(exitcode::OK);
+    }
+    print_changes(contents, &changes, display);
+    print_hashes(contents, mutated);
+    output::exit(WOULD_CHANGE);
+}
+
+fn print_hashes(contents: &str, mutated: &str) {
+    outln!(
+        "(Hash)\t {} -> {}",
+        content_hash(contents),
+        content_hash(mutated)
+    );
+}
+
+/// Overwrites the file with its mutated contents, journals the changes that got it there and
+/// stages the file for `--git-add`.
+fn write_contents(
+    file_path: String,
+    contents: &str,
+    snapshot: Snapshot,
+    mutated: &str,
+    changes: &[Change],
+    options: &WriteOptions,
+) {
+    let hashes = || (content_hash(contents), content_hash(mutated));
+    write_file(
+        &file_path,
+        snapshot,
+        Written::Contents(mutated),
+        hashes,
+        changes,
+        options,
+    );
+}
+
+/// How `write_file` puts the mutation into the file.
+enum Written<'a> {
+    /// The whole mutated contents, in place of the old ones.
+    Contents(&'a str),
+    /// Only what goes at the end of the file, which is left as it is up to there.
+    Appended(&'a str),
+}
+
+/// Writes the file once it's created for `--create`, locked, still looks like the snapshot and had
+/// its symlink replaced as asked. Then journals the changes with the hashes of the file before and
+/// after them, which are only worked out when they're journaled, and stages the file.
+fn write_file<H: FnOnce() -> (String, String)>(
+    file_path: &str,
+    snapshot: Snapshot,
+    written: Written,
+    hashes: H,
+    changes: &[Change],
+    options: &WriteOptions,
+) {
+    let snapshot = if snapshot.missing {
+        create_file(file_path, options);
+        Snapshot::of(&fs::metadata(file_path).unwrap())
+    } else {
+        snapshot
+    };
+    let snapshot = snapshot.locked(file_path, options);
+    snapshot.verify(file_path);
+    if options.replace_symlink {
+        replace_symlink(file_path);
+    }
+    // writing in place keeps the inode, so hard links, the mode, owner and extended attributes all
+    // stay as they were
+    let (mut file, bytes) = match written {
+        Written::Contents(mutated) => (file_from_dry_run(file_path.to_owned(), false), mutated),
+        Written::Appended(appended) => (
+            fs::OpenOptions::new().append(true).open(file_path).unwrap(),
+            appended,
+        ),
+    };
+    file.write_all(bytes.as_bytes()).unwrap();
+    file.flush().unwrap();
+    restore_mtime(&file, &snapshot, options);
+    if options.journal && !changes.is_empty() {
+        let (before, after) = hashes();
+        journal::record(file_path, before, after, changes);
+    }
+    if options.git_add {
+        git::stage(file_path);
+    }
+}
+
+/// `sha256:` followed by the hex digest of the contents.
+fn content_hash(contents: &str) -> String {
+    hash_hex(Sha256::digest(contents.as_bytes()).as_slice())
+}
+
+fn hash_hex(digest: &[u8]) -> String {
+    let hex: String = digest.iter().map(|byte| format!("{:02x}", byte)).collect();
+    format!("sha256:{}", hex)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        fs::{self, File},
+        io::Write,
+    };
+    use tempfile::NamedTempFile;
+
+    use crate::cli::parser::{
+        ColorWhen, DisplayOptions, ExpectedMatches, MatchOptions, PatternMutation, WriteOptions,
+    };
+    use crate::cmd::{
+        add::{append_entry, overwrite_pattern, prepend_entry},
+        lock_file,
+        matcher::LineMatcher,
+        output::capture,
+        read_file, WOULD_CHANGE,
+    };
+
+    #[test]
+    fn test_lock_file() {
+        // create test file
+        let mut file1 = NamedTempFile::new().unwrap();
+        file1.write_all("a\nb".as_bytes()).unwrap();
+        file1.flush().unwrap();
+        let file_path = file1.path().to_str().unwrap().to_owned();
+        // a second lock can only be taken once the first is dropped
+        let lock = lock_file(&file_path, 0);
+        assert!(File::open(&file_path).unwrap().try_lock().is_err());
+        drop(lock);
+        assert!(File::open(&file_path).unwrap().try_lock().is_ok());
+        // interactive runs only lock the file once they are about to write it
+        let options = WriteOptions {
+            interactive: true,
+            lock: true,
+            lock_timeout: 0,
+            ..Default::default()
+        };
+        let (_, snapshot) = read_file(&file_path, &options);
+        assert!(File::open(&file_path).unwrap().try_lock().is_ok());
+        let _snapshot = snapshot.locked(&file_path, &options);
+        assert!(File::open(&file_path).unwrap().try_lock().is_err());
+    }
+
+    #[test]
+    fn test_create_missing_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("conf.d").join("new.toml");
+        // mutate file
+        let options = WriteOptions {
+            create: true,
+            parents: true,
+            ..Default::default()
+        };
+        append_entry(
+            file.to_str().unwrap().to_owned(),
+            "entry=1".to_owned(),
+            &options,
+        );
+        // compare results
+        assert_eq!("entry=1", fs::read_to_string(&file).unwrap());
+        // an add that fails leaves no file behind
+        let missing = dir.path().join("conf.d").join("missing.toml");
+        let missing_path = missing.to_str().unwrap().to_owned();
+        let pattern_mut = PatternMutation {
+            pattern: "^entry".to_owned(),
+            ignore_comments: None,
+            options: MatchOptions::default(),
+            expected: ExpectedMatches::default(),
+        };
+        let outcome = capture(true, || {
+            overwrite_pattern(
+                missing_path.clone(),
+                &LineMatcher::for_file(&pattern_mut, &missing_path),
+                "entry=1".to_owned(),
+                &options,
+            )
+        });
+        assert_eq!(exitcode::DATAERR, outcome.code);
+        assert!(!missing.exists());
+    }
+
+    #[test]
+    fn test_check() {
+        // create test file
+        let contents = "log_level = \"info\"\nport = 80\n";
+        let mut file1 = NamedTempFile::new().unwrap();
+        file1.write_all(contents.as_bytes()).unwrap();
+        file1.flush().unwrap();
+        let file_path = file1.path().to_str().unwrap().to_owned();
+        let pattern_mut = PatternMutation {
+            pattern: "^log_level =".to_owned(),
+            ignore_comments: None,
+            options: MatchOptions::default(),
+            expected: ExpectedMatches::default(),
+        };
+        let options = WriteOptions {
+            check: true,
+            display: DisplayOptions {
+                color: ColorWhen::Never,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let check = |entry: &str| {
+            let matcher = LineMatcher::for_file(&pattern_mut, &file_path);
+            capture(true, || {
+                overwrite_pattern(file_path.clone(), &matcher, entry.to_owned(), &options)
+            })
+        };
+        // a file that already conforms passes quietly
+        let outcome = check("log_level = \"info\"");
+        assert_eq!(exitcode::OK, outcome.code);
+        assert_eq!("", outcome.stdout);
+        // a file that would change fails with the diff
+        let outcome = check("log_level = \"debug\"");
+        assert_eq!(WOULD_CHANGE, outcome.code);
+        assert!(outcome.stdout.contains("--- log_level = \"info\""));
+        assert!(outcome.stdout.contains("+++ log_level = \"debug\""));
+        assert!(outcome.stdout.contains("(Hash)"));
+        // compare results
+        assert_eq!(contents, fs::read_to_string(&file_path).unwrap());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_symlinks() {
+        // create test file and a link to it
+        let dir = tempfile::tempdir().unwrap();
+        let target = dir.path().join("target.toml");
+        let link = dir.path().join("link.toml");
+        fs::write(&target, "a").unwrap();
+        std::os::unix::fs::symlink(&target, &link).unwrap();
+        let link_path = link.to_str().unwrap().to_owned();
+        // the target is mutated through the link by default
+        prepend_entry(link_path.clone(), "b".to_owned(), &WriteOptions::default());
+        assert_eq!("b\na", fs::read_to_string(&target).unwrap());
+        // replacing the link leaves the target alone, and any file next to the link too
+        fs::write(dir.path().join("link.toml.mute"), "keep").unwrap();
+        let options = WriteOptions {
+            replace_symlink: true,
+            ..Default::default()
+        };
+        prepend_entry(link_path, "c".to_owned(), &options);
+        assert_eq!("b\na", fs::read_to_string(&target).unwrap());
+        assert_eq!("c\nb\na", fs::read_to_string(&link).unwrap());
+        assert_eq!(
+            "keep",
+            fs::read_to_string(dir.path().join("link.toml.mute")).unwrap()
+        );
+        assert_eq!(3, fs::read_dir(dir.path()).unwrap().count());
+        assert!(!fs::symlink_metadata(&link)
+            .unwrap()
+            .file_type()
+            .is_symlink());
+    }
+}
+
 pub mod add;
+pub mod change;
+pub mod comment;
+pub mod engine;
+pub mod entry;
+pub mod git;
+pub mod interactive;
+pub mod journal;
+pub mod matcher;
+pub mod output;
+pub mod parallel;
+pub mod patch;
+pub mod plan;
+pub mod recipe;
+pub mod relocate;
 pub mod remove;
+pub mod render;
+pub mod report;
+pub mod sort;
@@ -0,0 +1,126 @@
+use std::{
+    cell::{Cell, RefCell},
+    panic::{self, AssertUnwindSafe},
+    process,
+    sync::Once,
+};
+
+thread_local! {
+    /// Whether this thread mutates a file for a run over several files, so exiting only ends the
+    /// mutation of that file.
+    static CAPTURING: Cell<bool> = const { Cell::new(false) };
+    /// The report of the file this thread mutates, when it's printed once the reports of the files
+    /// before it have been.
+    static BUFFER: RefCell<Option<String>> = const { RefCell::new(None) };
+}
+
+/// Prints a line of a report, like `println!`, unless the report is being buffered by `capture`.
+macro_rules! outln {
+    () => {
+        $crate::cmd::output::write_line(String::new())
+    };
+    ($($arg:tt)*) => {
+        $crate::cmd::output::write_line(format!($($arg)*))
+    };
+}
+pub(crate) use outln;
+
+pub fn write_line(line: String) {
+    let buffered = BUFFER.with_borrow_mut(|buffer| match buffer {
+        Some(buffer) => {
+            buffer.push_str(&line);
+            buffer.push('\n');
+            true
+        }
+        None => false,
+    });
+    if !buffered {
+        println!("{}", line);
+    }
+}
+
+/// The payload unwinding a captured mutation that exits.
+struct Exit(i32);
+
+/// Exits with the code, or only ends the mutation being captured on this thread.
+pub fn exit(code: i32) -> ! {
+    if CAPTURING.get() {
+        panic::resume_unwind(Box::new(Exit(code)));
+    }
+    process::exit(code)
+}
+
+/// How a captured mutation went.
+#[derive(Debug)]
+pub struct Outcome {
+    pub code: i32,
+    /// The report, when it was buffered.
+    pub stdout: String,
+    /// What the mutation panicked with, if it did.
+    pub stderr: String,
+}
+
+/// Runs a mutation that may exit, returning its exit code instead of exiting, along with its
+/// report when `buffer` is set. Otherwise the report is printed as it goes, e.g. so an interactive
+/// mutation can talk to the terminal.
+pub fn capture<F: FnOnce()>(buffer: bool, mutate: F) -> Outcome {
+    static QUIET_PANICS: Once = Once::new();
+    QUIET_PANICS.call_once(|| {
+        // a panic is reported along with the rest of the report instead
+        let default_hook = panic::take_hook();
+        panic::set_hook(Box::new(move |info| {
+            if !CAPTURING.get() {
+                default_hook(info);
+            }
+        }));
+    });
+
+    // the capture this one is nested in, if any, carries on afterwards
+    let was_capturing = CAPTURING.replace(true);
+    let outer_buffer = BUFFER.replace(buffer.then(String::new));
+    let result = panic::catch_unwind(AssertUnwindSafe(mutate));
+    CAPTURING.set(was_capturing);
+    let stdout = BUFFER.replace(outer_buffer).unwrap_or_default();
+
+    let (code, stderr) = match result {
+        Ok(()) => (exitcode::OK, String::new()),
+        Err(payload) => match payload.downcast::<Exit>() {
+            Ok(exit) => (exit.0, String::new()),
+            Err(payload) => {
+                let message = payload
+                    .downcast_ref::<&str>()
+                    .map(|message| message.to_string())
+                    .or_else(|| payload.downcast_ref::<String>().cloned())
+                    .unwrap_or_default();
+                // the exit code of a panicking process
+                (101, message + "\n")
+            }
+        },
+    };
+    Outcome {
+        code,
+        stdout,
+        stderr,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::cmd::output::{capture, exit};
+
+    #[test]
+    fn test_capture() {
+        let outcome = capture(true, || {
+            outln!("WARNING: Pattern was not found.");
+            exit(65);
+        });
+        assert_eq!(65, outcome.code);
+        assert_eq!("WARNING: Pattern was not found.\n", outcome.stdout);
+
+        let outcome = capture(true, || panic!("Must provide a file to mutate."));
+        assert_eq!(101, outcome.code);
+        assert_eq!("Must provide a file to mutate.\n", outcome.stderr);
+        // printing goes back to normal afterwards
+        outln!("(not captured)");
+    }
+}
@@ -0,0 +1,223 @@
+use std::{
+    collections::BTreeMap,
+    fs,
+    io::{self, Write},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        mpsc,
+    },
+    thread,
+};
+
+use super::{
+    output::{capture, exit, Outcome},
+    plan::join_plans,
+    report::{write_report, TestCase},
+};
+use crate::cli::parser::{PlanFile, Report, WriteOptions};
+
+/// Mutates every file with `mutate`, on up to `jobs` threads at a time. The report of each file is
+/// buffered and printed once the reports of all the files before it have been, so the output
+/// doesn't depend on which file is done first. Exits with the exit code of the first file that
+/// failed, if any did.
+///
+/// Interactive runs need the terminal, so their files are mutated one after the other and talk to
+/// it directly. With `--emit-patch` or `mute plan`, each file gets a patch or plan of its own and
+/// they're joined into one, in order, at the end. With `--report`, every file is a test case named
+/// after it and the `operation`, and TAP reports are printed instead of the reports of the files.
+pub fn mutate_files<F>(
+    files: &[String],
+    jobs: usize,
+    operation: &str,
+    options: &WriteOptions,
+    mutate: F,
+) -> !
+where
+    F: Fn(String, &WriteOptions) + Sync,
+{
+    // the parts get a directory of their own, so they never clobber or leave behind files next to
+    // the patch or plan
+    let parts = tempfile::tempdir().unwrap();
+    let part_path = |index: usize| parts.path().join(index.to_string()).display().to_string();
+    let mutate_file = |index: usize, buffer: bool| {
+        let file_options = WriteOptions {
+            emit_patch: options.emit_patch.as_ref().map(|_| part_path(index)),
+            plan: options.plan.as_ref().map(|plan_file| PlanFile {
+                path: part_path(index),
+                ..plan_file.clone()
+            }),
+            ..options.clone()
+        };
+        capture(buffer, || mutate(files[index].clone(), &file_options))
+    };
+
+    if options.interactive {
+        let exit_codes = (0..files.len()).map(|index| {
+            println!("(File: {})", files[index]);
+            let outcome = mutate_file(index, false);
+            eprint!("{}", outcome.stderr);
+            outcome.code
+        });
+        let exit_code = first_failure(exit_codes.collect());
+        // exiting skips destructors
+        parts.close().unwrap();
+        exit(exit_code);
+    }
+
+    let next_file = AtomicUsize::new(0);
+    let (sender, receiver) = mpsc::channel::<(usize, Outcome)>();
+    let outcomes = thread::scope(|scope| {
+        for _ in 0..jobs.min(files.len()) {
+            let sender = sender.clone();
+            let (next_file, mutate_file) = (&next_file, &mutate_file);
+            scope.spawn(move || loop {
+                let index = next_file.fetch_add(1, Ordering::SeqCst);
+                if index >= files.len() {
+                    break;
+                }
+                sender.send((index, mutate_file(index, true))).unwrap();
+            });
+        }
+        drop(sender);
+
+        // reports arrive as files are done, and wait here until it's their turn
+        let mut pending: BTreeMap<usize, Outcome> = BTreeMap::new();
+        let mut outcomes = Vec::with_capacity(files.len());
+        for (index, outcome) in receiver {
+            pending.insert(index, outcome);
+            while let Some(outcome) = pending.remove(&outcomes.len()) {
+                if options.report != Some(Report::Tap) {
+                    print_report(&files[outcomes.len()], &outcome);
+                }
+                outcomes.push(outcome);
+            }
+        }
+        outcomes
+    });
+    let part_paths: Vec<String> = (0..files.len()).map(part_path).collect();
+    if let Some(patch_path) = &options.emit_patch {
+        join_patches(patch_path, &part_paths);
+    }
+    if let Some(plan_file) = &options.plan {
+        join_plans(plan_file, &part_paths);
+    }
+    // exiting skips destructors
+    parts.close().unwrap();
+    if let Some(report) = &options.report {
+        let cases: Vec<TestCase> = files
+            .iter()
+            .zip(&outcomes)
+            .map(|(file, outcome)| {
+                TestCase::new(
+                    file,
+                    operation,
+                    outcome.code,
+                    &outcome.stdout,
+                    &outcome.stderr,
+                )
+            })
+            .collect();
+        write_report(report, &cases);
+    }
+    let exit_codes = outcomes.iter().map(|outcome| outcome.code);
+    exit(first_failure(exit_codes.collect()));
+}
+
+/// Joins the parts of the patch, skipping files that failed before writing one.
+fn join_patches(patch_path: &str, part_paths: &[String]) {
+    let mut patch = fs::File::create(patch_path).unwrap();
+    for part_path in part_paths {
+        if let Ok(part) = fs::read(part_path) {
+            patch.write_all(&part).unwrap();
+        }
+    }
+}
+
+fn print_report(file: &str, outcome: &Outcome) {
+    if outcome.stdout.is_empty() && outcome.stderr.is_empty() {
+        return;
+    }
+    println!("(File: {})", file);
+    print!("{}", outcome.stdout);
+    io::stdout().flush().unwrap();
+    eprint!("{}", outcome.stderr);
+}
+
+fn first_failure(exit_codes: Vec<i32>) -> i32 {
+    exit_codes
+        .into_iter()
+        .find(|&code| code != exitcode::OK)
+        .unwrap_or(exitcode::OK)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, io::Write, sync::Mutex};
+    use tempfile::NamedTempFile;
+
+    use crate::cli::parser::WriteOptions;
+    use crate::cmd::output::{capture, exit};
+    use crate::cmd::parallel::mutate_files;
+
+    #[test]
+    fn test_mutate_files() {
+        // create test files
+        let files: Vec<NamedTempFile> = (0..4)
+            .map(|index| {
+                let mut file = NamedTempFile::new().unwrap();
+                file.write_all(format!("{}\n", index).as_bytes()).unwrap();
+                file.flush().unwrap();
+                file
+            })
+            .collect();
+        let paths: Vec<String> = files
+            .iter()
+            .map(|file| file.path().to_str().unwrap().to_owned())
+            .collect();
+        // mutate files, one of them failing
+        let mutated = Mutex::new(Vec::new());
+        let options = WriteOptions::default();
+        let outcome = capture(false, || {
+            mutate_files(&paths, 2, "append", &options, |file_path, _| {
+                if file_path == paths[1] {
+                    exit(65);
+                }
+                fs::write(&file_path, "mutated\n").unwrap();
+                mutated.lock().unwrap().push(file_path);
+            })
+        });
+        // compare results
+        assert_eq!(65, outcome.code);
+        assert_eq!(3, mutated.lock().unwrap().len());
+        assert_eq!("1\n", fs::read_to_string(&paths[1]).unwrap());
+        assert_eq!("mutated\n", fs::read_to_string(&paths[3]).unwrap());
+    }
+
+    #[test]
+    fn test_join_patch_parts() {
+        // create test directory, with a file named like a part of the patch
+        let dir = tempfile::tempdir().unwrap();
+        let patch_path = dir.path().join("changes.patch").display().to_string();
+        fs::write(format!("{}.0", patch_path), "mine\n").unwrap();
+        // mutate files, each writing its part of the patch
+        let files = vec!["a.toml".to_owned(), "b.toml".to_owned()];
+        let options = WriteOptions {
+            emit_patch: Some(patch_path.clone()),
+            ..Default::default()
+        };
+        let outcome = capture(true, || {
+            mutate_files(&files, 2, "append", &options, |file_path, options| {
+                let part_path = options.emit_patch.as_ref().unwrap();
+                fs::write(part_path, format!("{}\n", file_path)).unwrap();
+            })
+        });
+        // compare results
+        assert_eq!(0, outcome.code);
+        assert_eq!("a.toml\nb.toml\n", fs::read_to_string(&patch_path).unwrap());
+        assert_eq!(
+            "mine\n",
+            fs::read_to_string(format!("{}.0", patch_path)).unwrap()
+        );
+        assert_eq!(2, fs::read_dir(dir.path()).unwrap().count());
+    }
+}
@@ -0,0 +1,341 @@
+use similar::TextDiff;
+use std::{fs, io::Write};
+
+use super::{
+    change::changes_between,
+    commit_files,
+    output::{self, outln},
+    plan::apply_plan,
+    read_file, FILE_CHANGED,
+};
+use crate::cli::parser::WriteOptions;
+
+/// Lines of unchanged context around each hunk, like `diff -u`.
+const CONTEXT_LINES: usize = 3;
+
+/// Writes what mutating the file would do as a unified diff for `--emit-patch`, leaving the file
+/// alone. The patch names the file the way it was given on the command line.
+pub fn emit_patch(patch_path: &str, file_path: &str, contents: &str, mutated: &str) {
+    let mut patch = fs::File::create(patch_path).unwrap();
+    patch
+        .write_all(unified_diff(file_path, contents, mutated).as_bytes())
+        .unwrap();
+}
+
+/// The unified diff between the contents and the mutated contents, empty when they're the same.
+pub fn unified_diff(file_path: &str, contents: &str, mutated: &str) -> String {
+    TextDiff::from_lines(contents, mutated)
+        .unified_diff()
+        .context_radius(CONTEXT_LINES)
+        .header(file_path, file_path)
+        .to_string()
+}
+
+/// A hunk of a unified diff. Lines keep their new lines, the last line of a file may not have one.
+#[derive(Debug, PartialEq)]
+struct Hunk {
+    /// Where the old lines start in the old file, 0-indexed.
+    old_start: usize,
+    /// Where the new lines start in the new file, 0-indexed.
+    new_start: usize,
+    old: Vec<String>,
+    new: Vec<String>,
+}
+
+/// The hunks of a unified diff for a single file.
+#[derive(Debug, PartialEq)]
+struct FilePatch {
+    file_path: String,
+    hunks: Vec<Hunk>,
+}
+
+/// Applies a unified diff, or a plan, to every file it names, or reverts it with `reverse`. Every file is
+/// checked against the patch before any is written, and the hunks have to match exactly where the
+/// patch says they are, so a file that drifted from what the patch was made against is left alone.
+pub fn apply_patch(patch_path: &str, reverse: bool, options: &WriteOptions) {
+    let Ok(patch) = fs::read_to_string(patch_path) else {
+        outln!(
+            "WARNING: The patch {} could not be read. Please check the path and try again.",
+            patch_path
+        );
+        output::exit(exitcode::NOINPUT);
+    };
+    // a unified diff can't start with a brace, so anything that does is a plan
+    if patch.trim_start().starts_with('{') {
+        return apply_plan(patch_path, &patch, reverse, options);
+    }
+    let file_patches = parse_patch(&patch).unwrap_or_else(|(line_no, message)| {
+        outln!(
+            "WARNING: Line {} of the patch {} is invalid: {}",
+            line_no,
+            patch_path,
+            message
+        );
+        output::exit(exitcode::DATAERR);
+    });
+
+    let files = file_patches
+        .into_iter()
+        .map(|file_patch| {
+            let (contents, snapshot) = read_file(&file_patch.file_path, options);
+            let hunks = if reverse {
+                file_patch.hunks.into_iter().map(Hunk::reversed).collect()
+            } else {
+                file_patch.hunks
+            };
+            let mutated = patched(&contents, &hunks).unwrap_or_else(|line_no| {
+                outln!(
+                    "WARNING: {} has drifted from the patch at line {}. Nothing was changed.",
+                    file_patch.file_path,
+                    line_no
+                );
+                output::exit(FILE_CHANGED);
+            });
+            let changes = changes_between(&contents, &mutated);
+            (file_patch.file_path, contents, snapshot, changes)
+        })
+        .collect();
+    commit_files(files, options);
+}
+
+impl Hunk {
+    fn reversed(self) -> Hunk {
+        Hunk {
+            old_start: self.new_start,
+            new_start: self.old_start,
+            old: self.new,
+            new: self.old,
+        }
+    }
+}
+
+/// The file with the hunks applied. Errs with the 1-indexed line where the file no longer looks
+/// like the patch expects.
+fn patched(contents: &str, hunks: &[Hunk]) -> Result<String, usize> {
+    let lines: Vec<&str> = contents.split_inclusive('\n').collect();
+    let mut mutated = String::with_capacity(contents.len());
+    let mut next_line = 0;
+    for hunk in hunks {
+        let end = hunk.old_start + hunk.old.len();
+        let matches = hunk.old_start >= next_line
+            && end <= lines.len()
+            && lines[hunk.old_start..end].iter().eq(hunk.old.iter());
+        if !matches {
+            return Err(hunk.old_start + 1);
+        }
+        mutated.extend(lines[next_line..hunk.old_start].iter().copied());
+        mutated.extend(hunk.new.iter().map(|line| line.as_str()));
+        next_line = end;
+    }
+    mutated.extend(lines[next_line..].iter().copied());
+    Ok(mutated)
+}
+
+/// Splits a unified diff into the hunks for each file. Anything outside of the hunks other than
+/// the `---` and `+++` lines naming the file, like the `diff` command or git's extended headers,
+/// is skipped. Errs with the 1-indexed line of the patch that doesn't make sense.
+fn parse_patch(patch: &str) -> Result<Vec<FilePatch>, (usize, String)> {
+    // splitting on new lines alone keeps the carriage returns of files with CRLF line endings
+    let lines: Vec<&str> = patch.split_terminator('\n').collect();
+    let mut file_patches: Vec<FilePatch> = Vec::new();
+    let mut index = 0;
+    while index < lines.len() {
+        let line = lines[index];
+        if let Some(old_path) = line.strip_prefix("--- ") {
+            let Some(new_path) = lines
+                .get(index + 1)
+                .and_then(|line| line.strip_prefix("+++ "))
+            else {
+                return Err((
+                    index + 2,
+                    "expected a `+++` line naming the file".to_owned(),
+                ));
+            };
+            file_patches.push(FilePatch {
+                file_path: patch_path(old_path, new_path),
+                hunks: Vec::new(),
+            });
+            index += 2;
+        } else if line.starts_with("@@") {
+            let Some(file_patch) = file_patches.last_mut() else {
+                return Err((
+                    index + 1,
+                    "a hunk needs `---` and `+++` lines first".to_owned(),
+                ));
+            };
+            let (hunk, consumed) = parse_hunk(&lines[index..])
+                .map_err(|(offset, message)| (index + offset + 1, message))?;
+            file_patch.hunks.push(hunk);
+            index += consumed;
+        } else {
+            index += 1;
+        }
+    }
+    // an empty patch, like the one for a mutation that changed nothing, has nothing to apply
+    if file_patches.is_empty() && !patch.trim().is_empty() {
+        return Err((1, "there are no files in the patch".to_owned()));
+    }
+    let file_patches = merge_files(file_patches);
+    for file_patch in &file_patches {
+        let overlapping = file_patch
+            .hunks
+            .windows(2)
+            .any(|pair| pair[0].old_start + pair[0].old.len() > pair[1].old_start);
+        if overlapping {
+            return Err((1, format!("the hunks for {} overlap", file_patch.file_path)));
+        }
+    }
+    Ok(file_patches)
+}
+
+/// Reads one hunk, returning how many lines of the patch it took up.
+fn parse_hunk(lines: &[&str]) -> Result<(Hunk, usize), (usize, String)> {
+    let ((old_start, old_count), (new_start, new_count)) = parse_ranges(lines[0])
+        .ok_or((0, "expected `@@ -start,count +start,count @@`".to_owned()))?;
+    let mut hunk = Hunk {
+        old_start,
+        new_start,
+        old: Vec::new(),
+        new: Vec::new(),
+    };
+    let mut index = 1;
+    while hunk.old.len() < old_count || hunk.new.len() < new_count {
+        let Some(line) = lines.get(index) else {
+            return Err((index, "the hunk ends before all of its lines".to_owned()));
+        };
+        let marker = line.chars().next();
+        let text = format!("{}\n", &line[marker.map_or(0, char::len_utf8)..]);
+        match marker {
+            // some editors strip the space off of empty context lines
+            Some(' ') | None => {
+                hunk.old.push(text.clone());
+                hunk.new.push(text);
+            }
+            Some('-') => hunk.old.push(text),
+            Some('+') => hunk.new.push(text),
+            _ => return Err((index, format!("unexpected line `{}` in the hunk", line))),
+        }
+        index += 1;
+        // `\ No newline at end of file` means the line before it ends the file without one
+        if lines.get(index).is_some_and(|line| line.starts_with('\\')) {
+            let sides = match marker {
+                Some('-') => vec![&mut hunk.old],
+                Some('+') => vec![&mut hunk.new],
+                _ => vec![&mut hunk.old, &mut hunk.new],
+            };
+            for side in sides {
+                side.last_mut().unwrap().pop();
+            }
+            index += 1;
+        }
+    }
+    if hunk.old.len() != old_count || hunk.new.len() != new_count {
+        return Err((0, "the hunk has more lines than its header says".to_owned()));
+    }
+    Ok((hunk, index))
+}
+
+/// The 0-indexed start and the number of lines of both sides of `@@ -1,3 +1,4 @@`.
+fn parse_ranges(header: &str) -> Option<((usize, usize), (usize, usize))> {
+    let ranges = header.strip_prefix("@@ -")?.split(" @@").next()?;
+    let (old, new) = ranges.split_once(" +")?;
+    let range = |range: &str| -> Option<(usize, usize)> {
+        let (start, count) = match range.split_once(',') {
+            Some((start, count)) => (start.parse().ok()?, count.parse().ok()?),
+            None => (range.parse().ok()?, 1),
+        };
+        // an empty side names the line it goes after rather than the line it starts at
+        let start: usize = if count == 0 {
+            start
+        } else {
+            start.checked_sub(1)?
+        };
+        Some((start, count))
+    };
+    Some((range(old)?, range(new)?))
+}
+
+/// The file a patch is for, preferring the new name. Timestamps after a tab and git's `a/` and
+/// `b/` prefixes are dropped.
+fn patch_path(old_path: &str, new_path: &str) -> String {
+    let old_path = old_path.split('\t').next().unwrap_or_default();
+    let new_path = new_path.split('\t').next().unwrap_or_default();
+    match (old_path.strip_prefix("a/"), new_path.strip_prefix("b/")) {
+        (Some(_), Some(new_path)) => new_path.to_owned(),
+        _ if new_path == "/dev/null" => old_path.to_owned(),
+        _ => new_path.to_owned(),
+    }
+}
+
+/// Joins the hunks of files named more than once, so each file is read and written once.
+fn merge_files(file_patches: Vec<FilePatch>) -> Vec<FilePatch> {
+    let mut merged: Vec<FilePatch> = Vec::new();
+    for file_patch in file_patches {
+        match merged
+            .iter_mut()
+            .find(|merged| merged.file_path == file_patch.file_path)
+        {
+            Some(existing) => existing.hunks.extend(file_patch.hunks),
+            None => merged.push(file_patch),
+        }
+    }
+    for file_patch in &mut merged {
+        file_patch.hunks.sort_by_key(|hunk| hunk.old_start);
+    }
+    merged
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::cmd::patch::{parse_patch, patched, unified_diff, Hunk};
+
+    #[test]
+    fn test_patch_round_trip() {
+        for (contents, mutated) in [
+            (
+                "a\nb\nc\nd\ne\nf\ng\nh\ni\nj\n",
+                "a\nB\nc\nd\ne\nf\ng\nh\nI\nj\n",
+            ),
+            ("a\nb", "a\nb\nc"),
+            ("a\nb", "a\nb\n"),
+            ("", "a\n"),
+        ] {
+            // write the patch
+            let patch = unified_diff("conf.toml", contents, mutated);
+            let file_patch = parse_patch(&patch).unwrap().remove(0);
+            assert_eq!("conf.toml", file_patch.file_path);
+            // apply it both ways
+            assert_eq!(Ok(mutated.to_owned()), patched(contents, &file_patch.hunks));
+            let reversed: Vec<Hunk> = file_patch.hunks.into_iter().map(Hunk::reversed).collect();
+            assert_eq!(Ok(contents.to_owned()), patched(mutated, &reversed));
+        }
+    }
+
+    #[test]
+    fn test_patch_drift() {
+        let patch = unified_diff("conf.toml", "a\nb\nc\n", "a\nB\nc\n");
+        let hunks = &parse_patch(&patch).unwrap()[0].hunks;
+        assert_eq!(Err(1), patched("a\nb\nC\n", hunks));
+        assert_eq!(Err(1), patched("a\nB\nc\n", hunks));
+    }
+
+    #[test]
+    fn test_git_patch() {
+        let patch = "diff --git a/src/conf.toml b/src/conf.toml\n\
+        index 0000000..1111111 100644\n\
+        --- a/src/conf.toml\n\
+        +++ b/src/conf.toml\n\
+        @@ -1 +1,2 @@\n \
+        a\n\
+        +b\n";
+        let file_patches = parse_patch(patch).unwrap();
+        assert_eq!("src/conf.toml", file_patches[0].file_path);
+        assert_eq!(
+            Ok("a\nb\n".to_owned()),
+            patched("a\n", &file_patches[0].hunks)
+        );
+        assert!(parse_patch("@@ -1 +1 @@\n-a\n+b\n").is_err());
+        // a line without a marker is refused, even when it starts with a multi-byte character
+        assert!(parse_patch("--- f\n+++ f\n@@ -1,2 +1,2 @@\n a\néb\n").is_err());
+    }
+}
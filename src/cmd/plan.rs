@@ -0,0 +1,284 @@
+use serde::{Deserialize, Serialize};
+use std::fs;
+
+use super::{
+    change::{apply_changes, changes_between, Change},
+    commit_files, content_hash,
+    journal::{restore, reverse_changes},
+    output::{self, outln},
+    read_file, FILE_CHANGED,
+};
+use crate::cli::parser::{PlanFile, WriteOptions};
+
+/// The changes a command would make, written by `mute plan` so they can be reviewed and then made
+/// by `mute apply` exactly as they were planned.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Plan {
+    /// The command that was planned, as it was given to `mute plan`.
+    pub arguments: Vec<String>,
+    pub files: Vec<PlannedFile>,
+}
+
+/// The changes planned for a single file.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PlannedFile {
+    pub file: String,
+    /// The hash of the file the changes were worked out against. The plan only applies to a file
+    /// that still has it.
+    pub before: String,
+    pub after: String,
+    /// Numbered against the file as it was planned.
+    pub changes: Vec<Change>,
+}
+
+/// Writes a plan holding the changes to a single file.
+pub fn write_plan(
+    plan_file: &PlanFile,
+    file_path: &str,
+    contents: &str,
+    mutated: &str,
+    changes: &[Change],
+) {
+    let plan = Plan {
+        arguments: plan_file.arguments.clone(),
+        files: vec![PlannedFile {
+            file: file_path.to_owned(),
+            before: content_hash(contents),
+            after: content_hash(mutated),
+            changes: changes.to_vec(),
+        }],
+    };
+    save_plan(&plan_file.path, &plan);
+}
+
+/// Joins the single file plans of a run over several files into one plan, in the order of the
+/// files. Parts that weren't written, because mutating their file failed, are skipped.
+pub fn join_plans(plan_file: &PlanFile, part_paths: &[String]) {
+    let mut plan = Plan {
+        arguments: plan_file.arguments.clone(),
+        files: Vec::new(),
+    };
+    for part_path in part_paths {
+        if let Ok(part) = fs::read_to_string(part_path) {
+            let part: Plan = serde_json::from_str(&part).unwrap();
+            plan.files.extend(part.files);
+        }
+    }
+    save_plan(&plan_file.path, &plan);
+}
+
+fn save_plan(plan_path: &str, plan: &Plan) {
+    fs::write(
+        plan_path,
+        serde_json::to_string_pretty(plan).unwrap() + "\n",
+    )
+    .unwrap();
+}
+
+/// Makes the changes a plan holds, or reverts them with `reverse`. Every file has to have the hash
+/// the plan expects (the one it was planned against, or the one it had after the plan was applied
+/// when reverting) before any file is written.
+pub fn apply_plan(plan_path: &str, plan: &str, reverse: bool, options: &WriteOptions) {
+    let plan: Plan = serde_json::from_str(plan).unwrap_or_else(|error| {
+        outln!("WARNING: The plan {} is invalid: {}", plan_path, error);
+        output::exit(exitcode::DATAERR);
+    });
+    let files = plan
+        .files
+        .into_iter()
+        .map(|planned| {
+            let (contents, snapshot) = read_file(&planned.file, options);
+            let (expected, mut changes) = if reverse {
+                (planned.after, reverse_changes(&planned.changes))
+            } else {
+                (planned.before.clone(), planned.changes)
+            };
+            let hash = content_hash(&contents);
+            if hash != expected {
+                outln!(
+                    "WARNING: {} has the hash {} instead of the {} the plan expects. Nothing was changed.",
+                    planned.file, hash, expected
+                );
+                output::exit(FILE_CHANGED);
+            }
+            if let Err(reason) = fit_changes(&contents, &changes) {
+                outln!(
+                    "WARNING: The plan {} doesn't fit {}: {}. Nothing was changed.",
+                    plan_path, planned.file, reason
+                );
+                output::exit(exitcode::DATAERR);
+            }
+            if reverse {
+                changes = reverting_changes(
+                    plan_path,
+                    &planned.file,
+                    &contents,
+                    changes,
+                    &planned.before,
+                );
+            }
+            (planned.file, contents, snapshot, changes)
+        })
+        .collect();
+    commit_files(files, options);
+}
+
+/// The changes that revert a planned file, which has the hash the plan left it with, back to the
+/// contents with the hash `before` it was planned against. Lines can't tell whether the file ended
+/// with a new line, so when the reverse changes get that wrong, the changes are worked out from
+/// the contents they should give back instead. Exits when no contents with that hash come back.
+fn reverting_changes(
+    plan_path: &str,
+    file_path: &str,
+    contents: &str,
+    reverse: Vec<Change>,
+    before: &str,
+) -> Vec<Change> {
+    match restore(contents, &reverse, before) {
+        Some(reverted) if apply_changes(contents, &reverse) == reverted => reverse,
+        Some(reverted) => changes_between(contents, &reverted),
+        None => {
+            outln!(
+                "WARNING: Reverting the plan {} doesn't give {} back the hash {} it had before. Nothing was changed.",
+                plan_path, file_path, before
+            );
+            output::exit(exitcode::DATAERR);
+        }
+    }
+}
+
+/// Checks the changes of a plan can be made to the contents: in order, without overlapping, within
+/// the file and removing the lines the file has where they say. A plan that was edited by hand may
+/// not, even when the file has the hash it expects.
+fn fit_changes(contents: &str, changes: &[Change]) -> Result<(), String> {
+    let lines: Vec<&str> = contents.split('\n').collect();
+    let mut next_line = 0;
+    for change in changes {
+        let end = change.line + change.removed.len();
+        if change.line < next_line {
+            return Err(format!(
+                "the change at line {} is out of order or overlaps the one before it",
+                change.line + 1
+            ));
+        }
+        if end > lines.len() {
+            return Err(format!(
+                "the change at line {} goes past the end of the file",
+                change.line + 1
+            ));
+        }
+        if !lines[change.line..end].iter().eq(change.removed.iter()) {
+            return Err(format!(
+                "the change at line {} removes lines the file doesn't have there",
+                change.line + 1
+            ));
+        }
+        next_line = end;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, io::Write};
+    use tempfile::NamedTempFile;
+
+    use crate::cli::parser::{PlanFile, WriteOptions};
+    use crate::cmd::{
+        change::{apply_changes, Change},
+        content_hash,
+        matcher::LineSpan,
+        output::capture,
+        plan::{apply_plan, fit_changes, write_plan},
+    };
+
+    #[test]
+    fn test_plan_round_trip() {
+        // create test file
+        let mut file1 = NamedTempFile::new().unwrap();
+        file1.write_all("a\nb\nc\n".as_bytes()).unwrap();
+        file1.flush().unwrap();
+        let file_path = file1.path().to_str().unwrap().to_owned();
+        // plan the change
+        let plan_file = NamedTempFile::new().unwrap();
+        let plan_path = plan_file.path().to_str().unwrap();
+        let changes = [Change::insert(1, "entry")];
+        let mutated = apply_changes("a\nb\nc\n", &changes);
+        let plan_file = PlanFile {
+            path: plan_path.to_owned(),
+            arguments: Vec::new(),
+        };
+        write_plan(&plan_file, &file_path, "a\nb\nc\n", &mutated, &changes);
+        assert_eq!("a\nb\nc\n", fs::read_to_string(&file_path).unwrap());
+        // apply it, then revert it
+        let plan = fs::read_to_string(plan_path).unwrap();
+        apply_plan(plan_path, &plan, false, &WriteOptions::default());
+        assert_eq!("a\nentry\nb\nc\n", fs::read_to_string(&file_path).unwrap());
+        apply_plan(plan_path, &plan, true, &WriteOptions::default());
+        assert_eq!("a\nb\nc\n", fs::read_to_string(&file_path).unwrap());
+    }
+
+    #[test]
+    fn test_plan_round_trip_of_last_line() {
+        // create test file, without a new line at its end
+        let mut file1 = NamedTempFile::new().unwrap();
+        file1.write_all("a\nb".as_bytes()).unwrap();
+        file1.flush().unwrap();
+        let file_path = file1.path().to_str().unwrap().to_owned();
+        // plan removing the last line, which leaves the new line before it
+        let plan_file = NamedTempFile::new().unwrap();
+        let plan_path = plan_file.path().to_str().unwrap();
+        let lines = ["a", "b"];
+        let changes = [Change::remove(&lines, LineSpan { start: 1, end: 1 })];
+        let mutated = apply_changes("a\nb", &changes);
+        let plan_file = PlanFile {
+            path: plan_path.to_owned(),
+            arguments: Vec::new(),
+        };
+        write_plan(&plan_file, &file_path, "a\nb", &mutated, &changes);
+        // apply it, then revert it back to the file without the new line
+        let plan = fs::read_to_string(plan_path).unwrap();
+        apply_plan(plan_path, &plan, false, &WriteOptions::default());
+        assert_eq!("a\n", fs::read_to_string(&file_path).unwrap());
+        apply_plan(plan_path, &plan, true, &WriteOptions::default());
+        assert_eq!("a\nb", fs::read_to_string(&file_path).unwrap());
+
+        // reverting refuses a plan that doesn't give back the hash the file had before it
+        apply_plan(plan_path, &plan, false, &WriteOptions::default());
+        let tampered = plan.replace(&content_hash("a\nb"), &content_hash("x"));
+        let outcome = capture(true, || {
+            apply_plan(plan_path, &tampered, true, &WriteOptions::default())
+        });
+        assert_eq!(exitcode::DATAERR, outcome.code);
+        assert_eq!("a\n", fs::read_to_string(&file_path).unwrap());
+    }
+
+    #[test]
+    fn test_fit_changes() {
+        let contents = "a\nb\nc\n";
+        let lines: Vec<&str> = contents.split('\n').collect();
+        let span = |start, end| LineSpan { start, end };
+        assert!(fit_changes(
+            contents,
+            &[Change::insert(0, "x"), Change::remove(&lines, span(0, 1))]
+        )
+        .is_ok());
+        // out of order
+        assert!(fit_changes(contents, &[Change::insert(2, "x"), Change::insert(1, "y")]).is_err());
+        // overlapping
+        assert!(fit_changes(
+            contents,
+            &[
+                Change::remove(&lines, span(0, 1)),
+                Change::remove(&lines, span(1, 2))
+            ]
+        )
+        .is_err());
+        // past the end of the file
+        let mut past_end = Change::remove(&lines, span(2, 3));
+        past_end.line = 3;
+        assert!(fit_changes(contents, &[past_end]).is_err());
+        // removing lines the file doesn't have
+        assert!(fit_changes(contents, &[Change::replace(&["x"], span(0, 0), "y")]).is_err());
+    }
+}
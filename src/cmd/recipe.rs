@@ -0,0 +1,152 @@
+use clap::Parser;
+use std::{fs, mem};
+
+use super::{
+    engine::{apply_operations, Operation},
+    output::{self, outln},
+};
+use crate::cli::parser::{RecipeCommand, RecipeStep, WriteOptions};
+
+/// Applies every operation listed in the recipe file to the file in one go.
+pub fn apply_recipe(file_path: String, recipe_path: &str, options: &WriteOptions) {
+    let operations = read_recipe(recipe_path);
+    apply_operations(file_path, &operations, options);
+}
+
+/// Reads one operation from each line of a recipe. Blank lines and lines starting with `#` are
+/// skipped.
+fn read_recipe(recipe_path: &str) -> Vec<Operation> {
+    let Ok(contents) = fs::read_to_string(recipe_path) else {
+        outln!(
+            "WARNING: The recipe {} could not be read. Please check the path and try again.",
+            recipe_path
+        );
+        output::exit(exitcode::NOINPUT);
+    };
+    contents
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty() && !line.trim_start().starts_with('#'))
+        .map(|(index, line)| {
+            parse_operation(line).unwrap_or_else(|message| {
+                outln!(
+                    "WARNING: Line {} of the recipe {} is invalid: {}",
+                    index + 1,
+                    recipe_path,
+                    message
+                );
+                output::exit(exitcode::DATAERR);
+            })
+        })
+        .collect()
+}
+
+fn parse_operation(line: &str) -> Result<Operation, String> {
+    let step = RecipeStep::try_parse_from(split_words(line)?).map_err(|error| {
+        // only the first line, clap follows it up with usage meant for the command line
+        let rendered = error.to_string();
+        let message = rendered.lines().next().unwrap_or_default();
+        message.trim_start_matches("error: ").to_owned()
+    })?;
+    match (step.entry, step.command) {
+        (None, RecipeCommand::Add(_)) => Err("an add operation needs an entry".to_owned()),
+        (Some(_), RecipeCommand::Remove(_)) => {
+            Err("a remove operation can't have an entry".to_owned())
+        }
+        (Some(entry), RecipeCommand::Add(where_)) => Ok(Operation::Add {
+            entry,
+            address: where_.command,
+        }),
+        (None, RecipeCommand::Remove(where_)) => Ok(Operation::Remove {
+            address: where_.command,
+        }),
+    }
+}
+
+/// Splits a recipe line into words the way a shell would: whitespace separates words, single
+/// quotes keep everything as is, and double quotes keep everything but escaped `"` and `\`.
+fn split_words(line: &str) -> Result<Vec<String>, String> {
+    let mut words = Vec::new();
+    let mut word = String::new();
+    let mut in_word = false;
+    let mut chars = line.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            c if c.is_whitespace() => {
+                if in_word {
+                    words.push(mem::take(&mut word));
+                    in_word = false;
+                }
+                continue;
+            }
+            '\'' => loop {
+                match chars.next() {
+                    Some('\'') => break,
+                    Some(c) => word.push(c),
+                    None => return Err("a single quote is never closed".to_owned()),
+                }
+            },
+            '"' => loop {
+                match chars.next() {
+                    Some('"') => break,
+                    Some('\\') => match chars.next() {
+                        Some(c @ ('"' | '\\')) => word.push(c),
+                        Some(c) => {
+                            word.push('\\');
+                            word.push(c);
+                        }
+                        None => return Err("a double quote is never closed".to_owned()),
+                    },
+                    Some(c) => word.push(c),
+                    None => return Err("a double quote is never closed".to_owned()),
+                }
+            },
+            '\\' => word.extend(chars.next()),
+            c => word.push(c),
+        }
+        in_word = true;
+    }
+    if in_word {
+        words.push(word);
+    }
+    Ok(words)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::cli::parser::WhereCommands;
+    use crate::cmd::engine::Operation;
+    use crate::cmd::recipe::{parse_operation, split_words};
+
+    #[test]
+    fn test_split_words() {
+        assert_eq!(
+            Ok(vec![
+                "NEW_ENTRY=\"1\"".to_owned(),
+                "add".to_owned(),
+                "after-pattern".to_owned(),
+                "^\\[\\[security]]".to_owned(),
+                String::new(),
+            ]),
+            split_words(r#" 'NEW_ENTRY="1"'  add after-pattern "^\[\[security]]" "" "#)
+        );
+        assert_eq!(Ok(vec!["a b\"c".to_owned()]), split_words(r#"a\ "b\"c""#));
+        assert!(split_words("'open").is_err());
+    }
+
+    #[test]
+    fn test_parse_operation() {
+        let operation = parse_operation("remove overwrite-pattern -i '^debug'").unwrap();
+        assert!(matches!(
+            operation,
+            Operation::Remove {
+                address: WhereCommands::OverwritePattern(pattern_mut)
+            } if pattern_mut.options.ignore_case
+        ));
+        assert!(parse_operation("add append").is_err());
+        // only adding can append or prepend
+        assert!(parse_operation("remove prepend")
+            .is_err_and(|message| message.contains("unrecognized subcommand 'prepend'")));
+        assert!(parse_operation("x add sideways").is_err());
+    }
+}
@@ -0,0 +1,345 @@
+use super::{
+    change::Change,
+    commit_changes, find_line, find_range,
+    matcher::{LineMatcher, LineSpan},
+    output::{self, outln},
+    pattern_not_found, read_file,
+};
+use crate::cli::parser::{DestinationAddress, MatchOptions, SourceAddress, WriteOptions};
+
+pub fn move_lines(
+    file_path: String,
+    source: &SourceAddress,
+    destination: &DestinationAddress,
+    match_options: &MatchOptions,
+    options: &WriteOptions,
+) {
+    relocate_lines(file_path, source, destination, match_options, true, options);
+}
+
+pub fn copy_lines(
+    file_path: String,
+    source: &SourceAddress,
+    destination: &DestinationAddress,
+    match_options: &MatchOptions,
+    options: &WriteOptions,
+) {
+    relocate_lines(
+        file_path,
+        source,
+        destination,
+        match_options,
+        false,
+        options,
+    );
+}
+
+pub fn swap_lines(
+    file_path: String,
+    first: &SourceAddress,
+    second: &SourceAddress,
+    match_options: &MatchOptions,
+    options: &WriteOptions,
+) {
+    let (contents, snapshot) = read_file(&file_path, options);
+    let lines: Vec<&str> = contents.split('\n').collect();
+    let mut spans = [
+        single_block(&contents, first, match_options),
+        single_block(&contents, second, match_options),
+    ];
+    spans.sort_by_key(|span| span.start);
+    if spans[0].end >= spans[1].start {
+        outln!("WARNING: The lines to swap overlap. Please check the addresses and try again.");
+        output::exit(exitcode::DATAERR);
+    }
+
+    let changes: Vec<Change> = [(spans[0], spans[1]), (spans[1], spans[0])]
+        .iter()
+        .map(|&(replaced, replacement)| Change {
+            added: lines[replacement.start..=replacement.end]
+                .iter()
+                .map(|line| line.to_string())
+                .collect(),
+            ..Change::remove(&lines, replaced)
+        })
+        .collect();
+    commit_changes(file_path, &contents, snapshot, &changes, options);
+}
+
+/// Inserts the source lines at every destination, removing them from where they were if `remove`.
+/// Every source line keeps its order, even when a pattern matches lines far apart.
+fn relocate_lines(
+    file_path: String,
+    source: &SourceAddress,
+    destination: &DestinationAddress,
+    match_options: &MatchOptions,
+    remove: bool,
+    options: &WriteOptions,
+) {
+    let (contents, snapshot) = read_file(&file_path, options);
+    let lines: Vec<&str> = contents.split('\n').collect();
+    let sources = source_spans(&contents, source, match_options);
+    let destinations = destination_lines(&contents, destination, match_options);
+
+    let inside_source = |line: &usize| {
+        sources
+            .iter()
+            .any(|span| span.start < *line && *line <= span.end)
+    };
+    if remove && destinations.iter().any(inside_source) {
+        outln!("WARNING: Cannot move lines inside of themselves. Please check the addresses and try again.");
+        output::exit(exitcode::DATAERR);
+    }
+
+    let block: Vec<&str> = sources
+        .iter()
+        .flat_map(|span| lines[span.start..=span.end].iter().copied())
+        .collect();
+    let mut changes: Vec<Change> = destinations
+        .into_iter()
+        .map(|line| Change::insert_lines(line, &block))
+        .collect();
+    if remove {
+        changes.extend(sources.iter().map(|&span| Change::remove(&lines, span)));
+    }
+    // inserts go before a removal starting on the same line
+    changes.sort_by_key(|change| (change.line, !change.removed.is_empty()));
+    commit_changes(file_path, &contents, snapshot, &changes, options);
+}
+
+fn source_spans(
+    contents: &str,
+    source: &SourceAddress,
+    match_options: &MatchOptions,
+) -> Vec<LineSpan> {
+    if let Some(line_no) = source.from_line {
+        let index = find_line(contents, line_no);
+        return vec![LineSpan {
+            start: index,
+            end: index,
+        }];
+    }
+    if let Some(range) = source.from_lines {
+        return vec![find_range(contents, range)];
+    }
+
+    let pattern = source.from_pattern.as_deref().unwrap();
+    let spans = LineMatcher::new(pattern, match_options).find_spans(contents);
+    if spans.is_empty() {
+        pattern_not_found();
+    }
+    spans
+}
+
+/// Swapping needs each address to select one unbroken block of lines.
+fn single_block(contents: &str, source: &SourceAddress, match_options: &MatchOptions) -> LineSpan {
+    let spans = source_spans(contents, source, match_options);
+    let is_unbroken = spans
+        .windows(2)
+        .all(|pair| pair[0].end + 1 == pair[1].start);
+    if !is_unbroken {
+        outln!("WARNING: A pattern to swap matched lines that are apart. Please check the regex and try again.");
+        output::exit(exitcode::DATAERR);
+    }
+    LineSpan {
+        start: spans[0].start,
+        end: spans[spans.len() - 1].end,
+    }
+}
+
+/// The lines new lines get inserted in front of.
+fn destination_lines(
+    contents: &str,
+    destination: &DestinationAddress,
+    match_options: &MatchOptions,
+) -> Vec<usize> {
+    if let Some(line_no) = destination.at_line {
+        return vec![find_line(contents, line_no)];
+    }
+
+    let lines: Vec<usize> = match (&destination.after_pattern, &destination.before_pattern) {
+        (Some(pattern), _) => LineMatcher::new(pattern, match_options)
+            .find_spans(contents)
+            .into_iter()
+            .map(|span| span.end + 1)
+            .collect(),
+        (None, Some(pattern)) => LineMatcher::new(pattern, match_options)
+            .find_spans(contents)
+            .into_iter()
+            .map(|span| span.start)
+            .collect(),
+        (None, None) => unreachable!("clap requires a destination"),
+    };
+    if lines.is_empty() {
+        pattern_not_found();
+    }
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Read, Write};
+    use tempfile::NamedTempFile;
+
+    use crate::cli::parser::{
+        DestinationAddress, LineRange, MatchOptions, SourceAddress, WriteOptions,
+    };
+    use crate::cmd::relocate::{copy_lines, move_lines, swap_lines};
+
+    const FAUX_FILE: &str = "[table]\n\
+    [[subtable1]]\n\
+    entry_1=\"one\"\n\
+    entry_2=\"two\"\n\
+    \n\
+    [[subtable2]]\n\
+    entry_4=\"four\"";
+
+    #[test]
+    fn test_move_lines() {
+        // create test file
+        let mut file1 = NamedTempFile::new().unwrap();
+        file1.write_all(FAUX_FILE.as_bytes()).unwrap();
+        file1.flush().unwrap();
+        let file_path = file1.path().to_str().unwrap().to_owned();
+        // mutate file
+        let source = SourceAddress {
+            from_pattern: Some("^entry_2".to_owned()),
+            ..Default::default()
+        };
+        let destination = DestinationAddress {
+            after_pattern: Some("^\\[\\[subtable2]]".to_owned()),
+            ..Default::default()
+        };
+        move_lines(
+            file_path,
+            &source,
+            &destination,
+            &MatchOptions::default(),
+            &WriteOptions::default(),
+        );
+        // read in file
+        let mut mutated_contents = String::new();
+        let mut file2 = file1.reopen().unwrap();
+        file2.read_to_string(&mut mutated_contents).unwrap();
+        // compare results
+        let expected = "[table]\n\
+        [[subtable1]]\n\
+        entry_1=\"one\"\n\
+        \n\
+        [[subtable2]]\n\
+        entry_2=\"two\"\n\
+        entry_4=\"four\"";
+        assert_eq!(expected, mutated_contents);
+    }
+
+    #[test]
+    fn test_move_last_line() {
+        for (contents, expected) in [("a\nb\nc", "c\na\nb"), ("a\nb\nc\n", "c\na\nb\n")] {
+            // create test file
+            let mut file1 = NamedTempFile::new().unwrap();
+            file1.write_all(contents.as_bytes()).unwrap();
+            file1.flush().unwrap();
+            let file_path = file1.path().to_str().unwrap().to_owned();
+            // mutate file
+            let source = SourceAddress {
+                from_line: Some(3),
+                ..Default::default()
+            };
+            let destination = DestinationAddress {
+                at_line: Some(1),
+                ..Default::default()
+            };
+            move_lines(
+                file_path,
+                &source,
+                &destination,
+                &MatchOptions::default(),
+                &WriteOptions::default(),
+            );
+            // read in file
+            let mut mutated_contents = String::new();
+            let mut file2 = file1.reopen().unwrap();
+            file2.read_to_string(&mut mutated_contents).unwrap();
+            // compare results, the file ends the way it did
+            assert_eq!(expected, mutated_contents);
+        }
+    }
+
+    #[test]
+    fn test_copy_lines() {
+        // create test file
+        let mut file1 = NamedTempFile::new().unwrap();
+        file1.write_all(FAUX_FILE.as_bytes()).unwrap();
+        file1.flush().unwrap();
+        let file_path = file1.path().to_str().unwrap().to_owned();
+        // mutate file
+        let source = SourceAddress {
+            from_lines: Some(LineRange { start: 3, end: 4 }),
+            ..Default::default()
+        };
+        let destination = DestinationAddress {
+            at_line: Some(-1),
+            ..Default::default()
+        };
+        copy_lines(
+            file_path,
+            &source,
+            &destination,
+            &MatchOptions::default(),
+            &WriteOptions::default(),
+        );
+        // read in file
+        let mut mutated_contents = String::new();
+        let mut file2 = file1.reopen().unwrap();
+        file2.read_to_string(&mut mutated_contents).unwrap();
+        // compare results
+        let expected = "[table]\n\
+        [[subtable1]]\n\
+        entry_1=\"one\"\n\
+        entry_2=\"two\"\n\
+        \n\
+        [[subtable2]]\n\
+        entry_1=\"one\"\n\
+        entry_2=\"two\"\n\
+        entry_4=\"four\"";
+        assert_eq!(expected, mutated_contents);
+    }
+
+    #[test]
+    fn test_swap_lines() {
+        // create test file
+        let mut file1 = NamedTempFile::new().unwrap();
+        file1.write_all(FAUX_FILE.as_bytes()).unwrap();
+        file1.flush().unwrap();
+        let file_path = file1.path().to_str().unwrap().to_owned();
+        // mutate file
+        let first = SourceAddress {
+            from_lines: Some(LineRange { start: 2, end: 4 }),
+            ..Default::default()
+        };
+        let second = SourceAddress {
+            from_pattern: Some("subtable2|entry_4".to_owned()),
+            ..Default::default()
+        };
+        swap_lines(
+            file_path,
+            &first,
+            &second,
+            &MatchOptions::default(),
+            &WriteOptions::default(),
+        );
+        // read in file
+        let mut mutated_contents = String::new();
+        let mut file2 = file1.reopen().unwrap();
+        file2.read_to_string(&mut mutated_contents).unwrap();
+        // compare results
+        let expected = "[table]\n\
+        [[subtable2]]\n\
+        entry_4=\"four\"\n\
+        \n\
+        [[subtable1]]\n\
+        entry_1=\"one\"\n\
+        entry_2=\"two\"";
+        assert_eq!(expected, mutated_contents);
+    }
+}
@@ -1,151 +1,89 @@
-use regex::Regex;
-use std::{
-    fs::{self},
-    io::Write, process,
+use super::{
+    change::Change,
+    commit_changes, find_line,
+    matcher::{LineMatcher, LineSpan},
+    pattern_not_found, read_file,
 };
+use crate::cli::parser::WriteOptions;
 
-use super::{file_from_dry_run, NEW_LINE};
+pub fn remove_after_pattern(file_path: String, matcher: &LineMatcher, options: &WriteOptions) {
+    let (contents, snapshot) = read_file(&file_path, options);
+    let lines: Vec<&str> = contents.split('\n').collect();
+    let changes: Vec<Change> = matcher
+        .find_spans(&contents)
+        .into_iter()
+        .filter(|span| span.end + 1 < lines.len())
+        .map(|span| {
+            let next_line = LineSpan {
+                start: span.end + 1,
+                end: span.end + 1,
+            };
+            Change::remove(&lines, next_line)
+                .matching(span)
+                .matched_by(matcher)
+        })
+        .collect();
 
-pub fn remove_after_pattern(file_path: String, pattern: String, dry_run: bool) {
-    let contents = fs::read_to_string(&file_path).unwrap();
-    let mut line_skipped = false;
-    let mut skip_next = false;
-    let mut file = file_from_dry_run(file_path, dry_run);
-
-    let regex = Regex::new(pattern.as_str()).unwrap();
-    let mut line_iter = contents.split('\n').enumerate().peekable();
-
-    while let Some((index, line)) = line_iter.next() {
-        if skip_next {
-            skip_next = false;
-            line_skipped = true;
-            if dry_run {
-                println!("(Line: {})\t --- {}", index + 1, line);
-            }
-        } else if !dry_run {
-            file.write_all(line.as_bytes()).unwrap();
-            if line_iter.peek().is_some() {
-                file.write_all(&[NEW_LINE]).unwrap();
-            }
-        }
-
-        if regex.is_match(line) {
-            skip_next = true;
-        }
-    }
-
-    file.flush().unwrap();
-    if !line_skipped {
-        println!("WARNING: Pattern was not found. Please check the file, the regex and try again.");
-        process::exit(exitcode::DATAERR);
+    if changes.is_empty() && !matcher.allows_no_match() {
+        pattern_not_found();
     }
+    commit_changes(file_path, &contents, snapshot, &changes, options);
 }
 
-pub fn remove_via_line_number(file_path: String, line_no: usize, dry_run: bool) {
-    assert_ne!(
-        line_no, 0,
-        "Line numbers are 1-indexed. Cannot have a line 0."
+pub fn remove_via_line_number(file_path: String, line_no: isize, options: &WriteOptions) {
+    let (contents, snapshot) = read_file(&file_path, options);
+    let index = find_line(&contents, line_no);
+    let lines: Vec<&str> = contents.split('\n').collect();
+    let span = LineSpan {
+        start: index,
+        end: index,
+    };
+    commit_changes(
+        file_path,
+        &contents,
+        snapshot,
+        &[Change::remove(&lines, span)],
+        options,
     );
-
-    let contents = fs::read_to_string(&file_path).unwrap();
-    let mut line_skipped = false;
-    let mut file = file_from_dry_run(file_path, dry_run);
-    let mut line_iter = contents.split('\n').enumerate().peekable();
-
-    while let Some((index, line)) = line_iter.next() {
-        if index + 1 == line_no {
-            line_skipped = true;
-            if dry_run {
-                println!("(Line: {})\t --- {}", line_no, line);
-            }
-        } else if !dry_run {
-            file.write_all(line.as_bytes()).unwrap();
-            if line_iter.peek().is_some() {
-                file.write_all(&[NEW_LINE]).unwrap();
-            }
-        }
-    }
-
-    file.flush().unwrap();
-    if !line_skipped {
-        println!("WARNING: Line was not found. Please check the file and try again.");
-        process::exit(exitcode::DATAERR);
-    }
 }
 
-pub fn remove_before_pattern(file_path: String, pattern: String, dry_run: bool) {
-    let contents = fs::read_to_string(&file_path).unwrap();
-    let mut line_skipped = false;
-    let mut skip_last = false;
-    let mut line_buffer = "";
-
-    let mut file = file_from_dry_run(file_path, dry_run);
-
-    let regex = Regex::new(pattern.as_str()).unwrap();
-
-    let line_iter = contents.split('\n').enumerate().peekable();
-    for (index, line) in line_iter {
-        if regex.is_match(line) && index > 0 {
-            skip_last = true;
-        }
+pub fn remove_before_pattern(file_path: String, matcher: &LineMatcher, options: &WriteOptions) {
+    let (contents, snapshot) = read_file(&file_path, options);
+    let lines: Vec<&str> = contents.split('\n').collect();
+    let changes: Vec<Change> = matcher
+        .find_spans(&contents)
+        .into_iter()
+        .filter(|span| span.start > 0)
+        .map(|span| {
+            let previous_line = LineSpan {
+                start: span.start - 1,
+                end: span.start - 1,
+            };
+            Change::remove(&lines, previous_line)
+                .matching(span)
+                .matched_by(matcher)
+        })
+        .collect();
 
-        if skip_last {
-            skip_last = false;
-            line_skipped = true;
-            if dry_run {
-                println!("(Line: {})\t --- {}", index, line_buffer);
-            }
-        } else if !dry_run && index > 0 {
-            file.write_all(line_buffer.as_bytes()).unwrap();
-            file.write_all(&[NEW_LINE]).unwrap();
-        }
-        line_buffer = line;
-    }
-
-    if !dry_run {
-        file.write_all(line_buffer.as_bytes()).unwrap();
-    }
-
-    file.flush().unwrap();
-    if !line_skipped {
-        println!("WARNING: Pattern was not found. Please check the file, the regex and try again.");
-        process::exit(exitcode::DATAERR);
+    if changes.is_empty() && !matcher.allows_no_match() {
+        pattern_not_found();
     }
+    commit_changes(file_path, &contents, snapshot, &changes, options);
 }
 
-pub fn remove_overwrite_pattern(file_path: String, pattern: String, dry_run: bool) {
-    let contents = fs::read_to_string(&file_path).unwrap();
-    let mut line_skipped = false;
-    let mut skip_this = false;
-    let mut file = file_from_dry_run(file_path, dry_run);
-
-    let regex = Regex::new(pattern.as_str()).unwrap();
-
-    let mut line_iter = contents.split('\n').enumerate().peekable();
-    while let Some((index, line)) = line_iter.next() {
-        if regex.is_match(line) {
-            line_skipped = true;
-            skip_this = true;
-        }
-
-        if skip_this {
-            skip_this = false;
-            if dry_run {
-                println!("(Line: {})\t --- {}", index + 1, line);
-            }
-        } else if !dry_run {
-            file.write_all(line.as_bytes()).unwrap();
-            if line_iter.peek().is_some() {
-                file.write_all(&[NEW_LINE]).unwrap();
-            }
-        }
-    }
+pub fn remove_overwrite_pattern(file_path: String, matcher: &LineMatcher, options: &WriteOptions) {
+    let (contents, snapshot) = read_file(&file_path, options);
+    let lines: Vec<&str> = contents.split('\n').collect();
+    let changes: Vec<Change> = matcher
+        .find_spans(&contents)
+        .into_iter()
+        .map(|span| Change::remove(&lines, span).matched_by(matcher))
+        .collect();
 
-    file.flush().unwrap();
-    if !line_skipped {
-        println!("WARNING: Pattern was not found. Please check the file, the regex and try again.");
-        process::exit(exitcode::DATAERR);
+    if changes.is_empty() && !matcher.allows_no_match() {
+        pattern_not_found();
     }
+    commit_changes(file_path, &contents, snapshot, &changes, options);
 }
 
 #[cfg(test)]
@@ -153,6 +91,9 @@ mod tests {
     use std::io::{Read, Write};
     use tempfile::NamedTempFile;
 
+    use crate::cli::parser::{MatchOptions, WriteOptions};
+    use crate::cmd::matcher::LineMatcher;
+
     use crate::cmd::remove::{
         remove_after_pattern, remove_before_pattern, remove_overwrite_pattern,
         remove_via_line_number,
@@ -174,7 +115,11 @@ mod tests {
         file1.flush().unwrap();
         let file_path = file1.path().to_str().unwrap().to_owned();
         // mutate file
-        remove_after_pattern(file_path, "^\\[\\[subtable2]]".to_owned(), false);
+        remove_after_pattern(
+            file_path,
+            &LineMatcher::new("^\\[\\[subtable2]]", &MatchOptions::default()),
+            &WriteOptions::default(),
+        );
         // read in file
         let mut mutated_contents = String::new();
         let mut file2 = file1.reopen().unwrap();
@@ -197,7 +142,7 @@ mod tests {
         file1.flush().unwrap();
         let file_path = file1.path().to_str().unwrap().to_owned();
         // mutate file
-        remove_via_line_number(file_path, 1, false);
+        remove_via_line_number(file_path, 1, &WriteOptions::default());
         // read in file
         let mut mutated_contents = String::new();
         let mut file2 = file1.reopen().unwrap();
@@ -220,7 +165,11 @@ mod tests {
         file1.flush().unwrap();
         let file_path = file1.path().to_str().unwrap().to_owned();
         // mutate file
-        remove_before_pattern(file_path, "^\\[\\[subtable1]]".to_owned(), false);
+        remove_before_pattern(
+            file_path,
+            &LineMatcher::new("^\\[\\[subtable1]]", &MatchOptions::default()),
+            &WriteOptions::default(),
+        );
         // read in file
         let mut mutated_contents = String::new();
         let mut file2 = file1.reopen().unwrap();
@@ -243,7 +192,11 @@ mod tests {
         file1.flush().unwrap();
         let file_path = file1.path().to_str().unwrap().to_owned();
         // mutate file
-        remove_overwrite_pattern(file_path, "^\\[table]".to_owned(), false);
+        remove_overwrite_pattern(
+            file_path,
+            &LineMatcher::new("^\\[table]", &MatchOptions::default()),
+            &WriteOptions::default(),
+        );
         // read in file
         let mut mutated_contents = String::new();
         let mut file2 = file1.reopen().unwrap();
@@ -257,4 +210,56 @@ mod tests {
         entry_4=\"four\"";
         assert_eq!(expected, mutated_contents);
     }
+
+    #[test]
+    fn test_rm_via_negative_line_number() {
+        // create test file
+        let mut file1 = NamedTempFile::new().unwrap();
+        file1
+            .write_all(format!("{}\n", FAUX_FILE).as_bytes())
+            .unwrap();
+        file1.flush().unwrap();
+        let file_path = file1.path().to_str().unwrap().to_owned();
+        // mutate file
+        remove_via_line_number(file_path, -2, &WriteOptions::default());
+        // read in file
+        let mut mutated_contents = String::new();
+        let mut file2 = file1.reopen().unwrap();
+        file2.read_to_string(&mut mutated_contents).unwrap();
+        // compare results
+        let expected = "[table]\n\
+        [[subtable1]]\n\
+        entry_1=\"one\"\n\
+        entry_2=\"two\"\n\
+        \n\
+        entry_4=\"four\"\n";
+        assert_eq!(expected, mutated_contents);
+    }
+
+    #[test]
+    fn test_rm_inverted_pattern() {
+        // create test file
+        let mut file1 = NamedTempFile::new().unwrap();
+        file1
+            .write_all("# keep\nentry_1=1\n  # keep too\nentry_2=2\n".as_bytes())
+            .unwrap();
+        file1.flush().unwrap();
+        let file_path = file1.path().to_str().unwrap().to_owned();
+        // mutate file
+        let options = MatchOptions {
+            invert_match: true,
+            ..Default::default()
+        };
+        remove_overwrite_pattern(
+            file_path,
+            &LineMatcher::new("^\\s*#", &options),
+            &WriteOptions::default(),
+        );
+        // read in file
+        let mut mutated_contents = String::new();
+        let mut file2 = file1.reopen().unwrap();
+        file2.read_to_string(&mut mutated_contents).unwrap();
+        // compare results
+        assert_eq!("# keep\n  # keep too\n", mutated_contents);
+    }
 }
@@ -0,0 +1,323 @@
+use regex::Regex;
+use similar::{capture_diff_slices, Algorithm, DiffOp};
+use std::{
+    env,
+    io::{self, IsTerminal},
+};
+
+use super::{change::Change, output::outln};
+use crate::cli::parser::{ColorWhen, DiffStyle, DisplayOptions};
+
+const RED: &str = "\x1b[31m";
+const GREEN: &str = "\x1b[32m";
+/// Bold and underlined, on top of the color of the line.
+const HIGHLIGHT: &str = "\x1b[1;4m";
+const RESET: &str = "\x1b[0m";
+
+/// The width side-by-side output assumes when it can't find out the width of the terminal.
+const DEFAULT_WIDTH: usize = 80;
+
+/// Whether `--color` asks for color, which by default depends on printing to a terminal.
+pub fn uses_color(display: &DisplayOptions) -> bool {
+    match display.color {
+        ColorWhen::Always => true,
+        ColorWhen::Never => false,
+        ColorWhen::Auto => io::stdout().is_terminal() && env::var_os("NO_COLOR").is_none(),
+    }
+}
+
+/// The width of the terminal, or of `COLUMNS` when not printing to one.
+pub fn terminal_width() -> usize {
+    terminal_size::terminal_size()
+        .map(|(width, _)| width.0 as usize)
+        .or_else(|| env::var("COLUMNS").ok()?.parse().ok())
+        .unwrap_or(DEFAULT_WIDTH)
+}
+
+/// Prints the lines of a dry-run in the `--diff-style` asked for, in color when `--color` says so.
+pub struct Painter {
+    color: bool,
+    style: DiffStyle,
+    width: usize,
+}
+
+impl Painter {
+    pub fn new(display: &DisplayOptions) -> Painter {
+        Painter {
+            color: uses_color(display),
+            style: display.diff_style,
+            width: terminal_width(),
+        }
+    }
+
+    /// An unchanged line, at `index` in the original file and numbered `line_no` in the mutated
+    /// one.
+    pub fn context(&self, index: usize, line_no: isize, line: &str, highlight: Option<&Regex>) {
+        match self.style {
+            DiffStyle::SideBySide => {
+                self.columns(Some((index + 1, line)), Some((line_no, line)), ' ')
+            }
+            _ => outln!(
+                "(Line: {})\t     {}",
+                line_no,
+                self.highlight(line, highlight, "")
+            ),
+        }
+    }
+
+    /// A change, with the added lines starting at `line_no` in the mutated file.
+    pub fn change(&self, change: &Change, line_no: isize, highlight: Option<&Regex>) {
+        let removed = change
+            .removed
+            .iter()
+            .enumerate()
+            .map(|(k, line)| (change.line + k + 1, line.as_str()));
+        let added = change
+            .added
+            .iter()
+            .enumerate()
+            .map(|(k, line)| (line_no + k as isize, line.as_str()));
+        match self.style {
+            DiffStyle::Line => {
+                removed.for_each(|(line_no, line)| self.removed(line_no, line, highlight));
+                added.for_each(|(line_no, line)| self.added(line_no, line));
+            }
+            DiffStyle::Word => {
+                // lines are paired up in order, whatever is left over is shown whole
+                let paired = change.removed.len().min(change.added.len());
+                for ((_, old), (line_no, new)) in removed.clone().zip(added.clone()) {
+                    self.words(line_no, old, new);
+                }
+                removed
+                    .skip(paired)
+                    .for_each(|(line_no, line)| self.removed(line_no, line, highlight));
+                added
+                    .skip(paired)
+                    .for_each(|(line_no, line)| self.added(line_no, line));
+            }
+            DiffStyle::SideBySide => {
+                let mut removed = removed.map(Some).chain(std::iter::repeat(None));
+                let mut added = added.map(Some).chain(std::iter::repeat(None));
+                for _ in 0..change.removed.len().max(change.added.len()) {
+                    let (old, new) = (removed.next().flatten(), added.next().flatten());
+                    let marker = match (old, new) {
+                        (Some(_), Some(_)) => '|',
+                        (Some(_), None) => '<',
+                        _ => '>',
+                    };
+                    self.columns(old, new, marker);
+                }
+            }
+        }
+    }
+
+    fn removed(&self, line_no: usize, line: &str, highlight: Option<&Regex>) {
+        let line = self.highlight(line, highlight, RED);
+        self.paint(RED, &format!("(Line: {})\t --- {}", line_no, line));
+    }
+
+    fn added(&self, line_no: isize, line: &str) {
+        self.paint(GREEN, &format!("(Line: {})\t +++ {}", line_no, line));
+    }
+
+    fn paint(&self, color: &str, text: &str) {
+        if self.color {
+            outln!("{}{}{}", color, text, RESET);
+        } else {
+            outln!("{}", text);
+        }
+    }
+
+    /// Marks what the regex matches in the line, going back to the color of the line after each
+    /// match.
+    fn highlight(&self, line: &str, regex: Option<&Regex>, color: &str) -> String {
+        let Some(regex) = regex.filter(|_| self.color) else {
+            return line.to_owned();
+        };
+        let mut highlighted = String::with_capacity(line.len());
+        let mut position = 0;
+        for found in regex.find_iter(line).filter(|found| !found.is_empty()) {
+            highlighted.push_str(&line[position..found.start()]);
+            highlighted.push_str(&format!(
+                "{}{}{}{}",
+                HIGHLIGHT,
+                found.as_str(),
+                RESET,
+                color
+            ));
+            position = found.end();
+        }
+        highlighted.push_str(&line[position..]);
+        highlighted
+    }
+
+    /// A line replacing another as a single line, with the words that changed marked. Removed
+    /// words are red and added words green, or wrapped in `[-...-]` and `{+...+}` without color.
+    fn words(&self, line_no: isize, old: &str, new: &str) {
+        let mut merged = String::with_capacity(old.len() + new.len());
+        for (tag, text) in word_diff(old, new) {
+            let (color, open, close) = match tag {
+                WordTag::Same => {
+                    merged.push_str(&text);
+                    continue;
+                }
+                WordTag::Removed => (RED, "[-", "-]"),
+                WordTag::Added => (GREEN, "{+", "+}"),
+            };
+            if self.color {
+                merged.push_str(&format!("{}{}{}", color, text, RESET));
+            } else {
+                merged.push_str(&format!("{}{}{}", open, text, close));
+            }
+        }
+        outln!("(Line: {})\t ~~~ {}", line_no, merged);
+    }
+
+    /// A row of side-by-side output: the original line on the left, the mutated one on the right.
+    fn columns(&self, old: Option<(usize, &str)>, new: Option<(isize, &str)>, marker: char) {
+        // 5 columns for each line number, 3 around the marker
+        let column = self.width.saturating_sub(13).max(20) / 2;
+        let (left, right) = (
+            fit(old.map_or("", |(_, line)| line), column),
+            fit(new.map_or("", |(_, line)| line), column),
+        );
+        let left_no = old.map_or(String::new(), |(line_no, _)| line_no.to_string());
+        let right_no = new.map_or(String::new(), |(line_no, _)| line_no.to_string());
+        let (left, right) = match (self.color, marker) {
+            (true, '|' | '<' | '>') => (
+                format!("{}{}{}", RED, left, RESET),
+                format!("{}{}{}", GREEN, right, RESET),
+            ),
+            _ => (left, right),
+        };
+        let row = format!(
+            "{:>5} {} {} {:>5} {}",
+            left_no, left, marker, right_no, right
+        );
+        outln!("{}", row.trim_end());
+    }
+}
+
+/// Pads or cuts the line to exactly `width` characters, so the columns line up. Tabs are expanded
+/// as they'd otherwise be as wide as the terminal likes.
+fn fit(line: &str, width: usize) -> String {
+    let line = line.replace('\t', "    ");
+    if line.chars().count() > width {
+        let cut: String = line.chars().take(width.saturating_sub(1)).collect();
+        format!("{}…", cut)
+    } else {
+        format!("{:<width$}", line, width = width)
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum WordTag {
+    Same,
+    Removed,
+    Added,
+}
+
+/// The difference between two lines, a word or a single symbol at a time, with neighbouring
+/// pieces of the same kind joined up.
+fn word_diff(old: &str, new: &str) -> Vec<(WordTag, String)> {
+    let (old_words, new_words) = (split_words(old), split_words(new));
+    let mut pieces: Vec<(WordTag, String)> = Vec::new();
+    let mut push = |tag: WordTag, words: &[&str]| {
+        let text = words.concat();
+        match pieces.last_mut() {
+            _ if text.is_empty() => {}
+            Some((last_tag, last_text)) if *last_tag == tag => last_text.push_str(&text),
+            _ => pieces.push((tag, text)),
+        }
+    };
+    for op in capture_diff_slices(Algorithm::Myers, &old_words, &new_words) {
+        match op {
+            DiffOp::Equal { .. } => push(WordTag::Same, &old_words[op.old_range()]),
+            _ => {
+                push(WordTag::Removed, &old_words[op.old_range()]);
+                push(WordTag::Added, &new_words[op.new_range()]);
+            }
+        }
+    }
+    pieces
+}
+
+/// Splits a line into words (runs of letters, digits and `_`), runs of whitespace, and single
+/// symbols, which joined back up give the line.
+fn split_words(line: &str) -> Vec<&str> {
+    let kind = |c: char| {
+        if c.is_alphanumeric() || c == '_' {
+            0
+        } else if c.is_whitespace() {
+            1
+        } else {
+            2
+        }
+    };
+    let mut words = Vec::new();
+    let mut start = 0;
+    let mut previous: Option<char> = None;
+    for (position, c) in line.char_indices() {
+        if let Some(previous) = previous {
+            if kind(c) != kind(previous) || kind(c) == 2 {
+                words.push(&line[start..position]);
+                start = position;
+            }
+        }
+        previous = Some(c);
+    }
+    if start < line.len() {
+        words.push(&line[start..]);
+    }
+    words
+}
+
+#[cfg(test)]
+mod tests {
+    use regex::Regex;
+
+    use crate::cli::parser::DiffStyle;
+    use crate::cmd::render::{fit, split_words, word_diff, Painter, WordTag, RED};
+
+    #[test]
+    fn test_highlight() {
+        let regex = Regex::new("[0-9]+").unwrap();
+        let mut painter = Painter {
+            color: true,
+            style: DiffStyle::Line,
+            width: 80,
+        };
+        assert_eq!(
+            "port = \x1b[1;4m80\x1b[0m\x1b[31m # or \x1b[1;4m8080\x1b[0m\x1b[31m",
+            painter.highlight("port = 80 # or 8080", Some(&regex), RED)
+        );
+        painter.color = false;
+        assert_eq!(
+            "port = 80",
+            painter.highlight("port = 80", Some(&regex), RED)
+        );
+    }
+
+    #[test]
+    fn test_word_diff() {
+        assert_eq!(
+            vec!["port", " ", "=", " ", "80", " ", "#", "#", " ", "is_it"],
+            split_words("port = 80 ## is_it")
+        );
+        assert_eq!(
+            vec![
+                (WordTag::Same, "version = \"0.".to_owned()),
+                (WordTag::Removed, "1".to_owned()),
+                (WordTag::Added, "2".to_owned()),
+                (WordTag::Same, ".0\"".to_owned()),
+            ],
+            word_diff("version = \"0.1.0\"", "version = \"0.2.0\"")
+        );
+    }
+
+    #[test]
+    fn test_fit() {
+        assert_eq!("ab  ", fit("ab", 4));
+        assert_eq!("abc…", fit("abcdef", 4));
+    }
+}
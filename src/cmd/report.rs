@@ -0,0 +1,307 @@
+use clap::{ArgMatches, CommandFactory};
+use regex::Regex;
+use std::{ffi::OsStr, fmt::Write, fs};
+
+use super::WOULD_CHANGE;
+use crate::cli::parser::{CLIArguments, Report};
+
+/// How mutating a single file went, as a test case of a report.
+#[derive(Debug)]
+pub struct TestCase {
+    file: String,
+    operation: String,
+    exit_code: i32,
+    /// What went wrong, for a file that failed.
+    failure: Option<String>,
+    /// What the run printed, without colors, e.g. the diff of a dry-run.
+    stdout: String,
+    stderr: String,
+}
+
+impl TestCase {
+    pub fn new(
+        file: &str,
+        operation: &str,
+        exit_code: i32,
+        stdout: &str,
+        stderr: &str,
+    ) -> TestCase {
+        let (stdout, stderr) = (strip_colors(stdout), strip_colors(stderr));
+        TestCase {
+            file: file.to_owned(),
+            operation: operation.to_owned(),
+            exit_code,
+            failure: failure_message(exit_code, &stdout, &stderr),
+            stdout,
+            stderr,
+        }
+    }
+}
+
+/// Writes the report of a run over files, a JUnit XML file or TAP printed on the standard output.
+/// A file passes when it was mutated, or already looked like the result, and fails otherwise.
+pub fn write_report(report: &Report, cases: &[TestCase]) {
+    match report {
+        Report::Junit(path) => fs::write(path, junit(cases)).unwrap(),
+        Report::Tap => print!("{}", tap(cases)),
+    }
+}
+
+/// Names the operation a run makes after its entry, its subcommands and their positional
+/// arguments, e.g. `"x = 1" add after-pattern ^\[deps]`, to name test cases after.
+pub fn operation_name(matches: &ArgMatches) -> String {
+    let quote = |argument: &OsStr| {
+        let argument = argument.to_string_lossy();
+        if argument.is_empty() || argument.contains(char::is_whitespace) {
+            format!("{:?}", argument)
+        } else {
+            argument.into_owned()
+        }
+    };
+    let root = CLIArguments::command();
+    let (mut command, mut matches) = (&root, matches);
+    let mut words: Vec<String> = matches
+        .get_raw("entry")
+        .into_iter()
+        .flatten()
+        .map(quote)
+        .collect();
+    while let Some((name, sub_matches)) = matches.subcommand() {
+        words.push(name.to_owned());
+        command = command.find_subcommand(name).unwrap();
+        for argument in command.get_positionals() {
+            let values = sub_matches.get_raw(argument.get_id().as_str());
+            words.extend(values.into_iter().flatten().map(quote));
+        }
+        matches = sub_matches;
+    }
+    words.join(" ")
+}
+
+/// The warning a failed run printed, or the end of its panic message, or else what its exit code
+/// means.
+fn failure_message(exit_code: i32, stdout: &str, stderr: &str) -> Option<String> {
+    if exit_code == exitcode::OK {
+        return None;
+    }
+    let warning = stdout
+        .lines()
+        .find_map(|line| line.strip_prefix("WARNING: "))
+        .or_else(|| {
+            stderr.lines().find(|line| {
+                !line.trim().is_empty()
+                    && !line.starts_with("thread '")
+                    && !line.starts_with("note:")
+            })
+        });
+    Some(match warning {
+        Some(warning) => warning.to_owned(),
+        None if exit_code == WOULD_CHANGE => "The file would change.".to_owned(),
+        None => format!("Exited with code {}.", exit_code),
+    })
+}
+
+fn strip_colors(text: &str) -> String {
+    let colors = Regex::new("\x1b\\[[0-9;]*m").unwrap();
+    colors.replace_all(text, "").into_owned()
+}
+
+fn junit(cases: &[TestCase]) -> String {
+    let failures = cases.iter().filter(|case| case.failure.is_some()).count();
+    let mut xml = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    writeln!(
+        xml,
+        "<testsuites tests=\"{}\" failures=\"{}\">",
+        cases.len(),
+        failures
+    )
+    .unwrap();
+    writeln!(
+        xml,
+        "  <testsuite name=\"mute\" tests=\"{}\" failures=\"{}\">",
+        cases.len(),
+        failures
+    )
+    .unwrap();
+    for case in cases {
+        writeln!(
+            xml,
+            "    <testcase classname=\"{}\" name=\"{}\">",
+            escape_xml(&case.operation),
+            escape_xml(&case.file)
+        )
+        .unwrap();
+        if let Some(failure) = &case.failure {
+            writeln!(
+                xml,
+                "      <failure message=\"{}\" type=\"exit code {}\"/>",
+                escape_xml(failure),
+                case.exit_code
+            )
+            .unwrap();
+        }
+        if !case.stdout.is_empty() {
+            writeln!(
+                xml,
+                "      <system-out>{}</system-out>",
+                escape_xml(&case.stdout)
+            )
+            .unwrap();
+        }
+        if !case.stderr.is_empty() {
+            writeln!(
+                xml,
+                "      <system-err>{}</system-err>",
+                escape_xml(&case.stderr)
+            )
+            .unwrap();
+        }
+        xml.push_str("    </testcase>\n");
+    }
+    xml.push_str("  </testsuite>\n</testsuites>\n");
+    xml
+}
+
+/// Escapes text for XML, dropping the control characters XML can't hold at all.
+fn escape_xml(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            '\t' | '\n' | '\r' => escaped.push(c),
+            c if c.is_control() => {}
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// TAP version 13. A failed test point has its message and exit code in a YAML block, and what the
+/// run printed follows every test point as comments.
+fn tap(cases: &[TestCase]) -> String {
+    let mut tap = format!("TAP version 13\n1..{}\n", cases.len());
+    for (index, case) in cases.iter().enumerate() {
+        // a `#` would start a directive, e.g. `# SKIP`
+        let description = format!("{}: {}", case.file, case.operation).replace('#', "\\#");
+        match &case.failure {
+            None => writeln!(tap, "ok {} - {}", index + 1, description).unwrap(),
+            Some(failure) => {
+                writeln!(tap, "not ok {} - {}", index + 1, description).unwrap();
+                tap.push_str("  ---\n");
+                // a JSON string is a valid YAML one
+                writeln!(
+                    tap,
+                    "  message: {}",
+                    serde_json::to_string(failure).unwrap()
+                )
+                .unwrap();
+                writeln!(tap, "  exit_code: {}", case.exit_code).unwrap();
+                tap.push_str("  ...\n");
+            }
+        }
+        for line in case.stdout.lines().chain(case.stderr.lines()) {
+            writeln!(tap, "# {}", line).unwrap();
+        }
+    }
+    tap
+}
+
+#[cfg(test)]
+mod tests {
+    use clap::CommandFactory;
+
+    use crate::cli::parser::CLIArguments;
+    use crate::cmd::report::{junit, operation_name, tap, TestCase};
+
+    fn test_cases() -> Vec<TestCase> {
+        vec![
+            TestCase::new(
+                "a.toml",
+                "\"x = 1\" add append",
+                0,
+                "(Line: 3)\t \x1b[32m+++ x = 1\x1b[0m\n",
+                "",
+            ),
+            TestCase::new(
+                "b&c.toml",
+                "remove overwrite-pattern ^#",
+                65,
+                "WARNING: Pattern was not found. Please check the file, the regex and try again.\n",
+                "",
+            ),
+        ]
+    }
+
+    #[test]
+    fn test_junit() {
+        let expected = r#"<?xml version="1.0" encoding="UTF-8"?>
+<testsuites tests="2" failures="1">
+  <testsuite name="mute" tests="2" failures="1">
+    <testcase classname="&quot;x = 1&quot; add append" name="a.toml">
+      <system-out>(Line: 3)	 +++ x = 1
+</system-out>
+    </testcase>
+    <testcase classname="remove overwrite-pattern ^#" name="b&amp;c.toml">
+      <failure message="Pattern was not found. Please check the file, the regex and try again." type="exit code 65"/>
+      <system-out>WARNING: Pattern was not found. Please check the file, the regex and try again.
+</system-out>
+    </testcase>
+  </testsuite>
+</testsuites>
+"#;
+        assert_eq!(expected, junit(&test_cases()));
+    }
+
+    #[test]
+    fn test_tap() {
+        let expected = r#"TAP version 13
+1..2
+ok 1 - a.toml: "x = 1" add append
+# (Line: 3)	 +++ x = 1
+not ok 2 - b&c.toml: remove overwrite-pattern ^\#
+  ---
+  message: "Pattern was not found. Please check the file, the regex and try again."
+  exit_code: 65
+  ...
+# WARNING: Pattern was not found. Please check the file, the regex and try again.
+"#;
+        assert_eq!(expected, tap(&test_cases()));
+    }
+
+    #[test]
+    fn test_failure_message() {
+        let case = TestCase::new("a", "", 1, "(Hash)\t sha256:1 -> sha256:2\n", "");
+        assert_eq!(Some("The file would change."), case.failure.as_deref());
+        let panicked = "thread 'main' panicked at src/main.rs:88:20:\nMust provide an entry to add while using the Add command.\nnote: run with `RUST_BACKTRACE=1`\n";
+        let case = TestCase::new("a", "", 101, "", panicked);
+        assert_eq!(
+            Some("Must provide an entry to add while using the Add command."),
+            case.failure.as_deref()
+        );
+    }
+
+    #[test]
+    fn test_operation_name() {
+        let arguments = [
+            "mute",
+            "-j",
+            "4",
+            "a.toml",
+            "--file",
+            "b.toml",
+            "x = 1",
+            "add",
+            "after-pattern",
+            "^\\[deps]",
+        ];
+        let matches = CLIArguments::command().get_matches_from(arguments);
+        assert_eq!(
+            "\"x = 1\" add after-pattern ^\\[deps]",
+            operation_name(&matches)
+        );
+    }
+}
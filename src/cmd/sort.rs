@@ -0,0 +1,330 @@
+use regex::Regex;
+use std::{cmp::Ordering, collections::HashSet};
+
+use super::{
+    change::Change,
+    commit_changes, find_range, line_count,
+    matcher::{compile_pattern, LineSpan},
+    pattern_not_found, read_file,
+};
+use crate::cli::parser::{Keep, Region, SortOrder, WriteOptions};
+
+pub fn sort_lines(file_path: String, region: &Region, order: &SortOrder, options: &WriteOptions) {
+    let (contents, snapshot) = read_file(&file_path, options);
+    let lines: Vec<&str> = contents.split('\n').collect();
+    let key = order.key.as_deref().map(compile_pattern);
+
+    let mut changes: Vec<Change> = Vec::new();
+    for span in region_spans(&contents, region) {
+        let mut sorted: Vec<&str> = lines[span.start..=span.end].to_vec();
+        sorted.sort_by(|a, b| {
+            let (a, b) = (
+                sort_key(a, key.as_ref(), order.ignore_case),
+                sort_key(b, key.as_ref(), order.ignore_case),
+            );
+            let ordering = if order.numeric {
+                compare_numeric(&a, &b)
+            } else if order.natural {
+                compare_natural(&a, &b)
+            } else {
+                a.cmp(&b)
+            };
+            if order.reverse {
+                ordering.reverse()
+            } else {
+                ordering
+            }
+        });
+
+        if sorted != lines[span.start..=span.end] {
+            changes.push(Change {
+                added: sorted.iter().map(|line| line.to_string()).collect(),
+                ..Change::remove(&lines, span)
+            });
+        }
+    }
+    commit_changes(file_path, &contents, snapshot, &changes, options);
+}
+
+pub fn dedupe_lines(
+    file_path: String,
+    region: &Region,
+    keep: Keep,
+    ignore_case: bool,
+    key: Option<String>,
+    options: &WriteOptions,
+) {
+    let (contents, snapshot) = read_file(&file_path, options);
+    let lines: Vec<&str> = contents.split('\n').collect();
+    let key = key.as_deref().map(compile_pattern);
+
+    let mut changes: Vec<Change> = Vec::new();
+    for span in region_spans(&contents, region) {
+        let mut indices: Vec<usize> = (span.start..=span.end).collect();
+        // keeping the last repeat is keeping the first one seen when walking backwards
+        if keep == Keep::Last {
+            indices.reverse();
+        }
+        let mut seen = HashSet::new();
+        let mut repeats: Vec<usize> = indices
+            .into_iter()
+            .filter(|&index| !seen.insert(sort_key(lines[index], key.as_ref(), ignore_case)))
+            .collect();
+        repeats.sort_unstable();
+
+        changes.extend(repeats.into_iter().map(|index| {
+            let span = LineSpan {
+                start: index,
+                end: index,
+            };
+            Change::remove(&lines, span)
+        }));
+    }
+    commit_changes(file_path, &contents, snapshot, &changes, options);
+}
+
+/// The blocks of lines a region covers. Blocks between patterns exclude the matching lines.
+fn region_spans(contents: &str, region: &Region) -> Vec<LineSpan> {
+    if let Some(range) = region.lines {
+        return vec![find_range(contents, range)];
+    }
+
+    let patterns = region.between.as_ref().unwrap();
+    let (start, end) = (compile_pattern(&patterns[0]), compile_pattern(&patterns[1]));
+    let lines: Vec<&str> = contents.split('\n').take(line_count(contents)).collect();
+    let mut spans = Vec::new();
+    let mut index = 0;
+    while index < lines.len() {
+        if !start.is_match(lines[index]) {
+            index += 1;
+            continue;
+        }
+        let first = index + 1;
+        let last = (first..lines.len())
+            .find(|&index| end.is_match(lines[index]))
+            .unwrap_or(lines.len());
+        if first < last {
+            spans.push(LineSpan {
+                start: first,
+                end: last - 1,
+            });
+        }
+        index = last;
+    }
+
+    if spans.is_empty() {
+        pattern_not_found();
+    }
+    spans
+}
+
+/// The part of a line lines are compared by.
+fn sort_key(line: &str, key: Option<&Regex>, ignore_case: bool) -> String {
+    let key = key
+        .and_then(|key| key.captures(line))
+        .and_then(|captures| captures.get(1).or_else(|| captures.get(0)))
+        .map_or(line, |found| found.as_str());
+    if ignore_case {
+        key.to_lowercase()
+    } else {
+        key.to_owned()
+    }
+}
+
+/// Orders by the number a key starts with. Keys without one come first.
+fn compare_numeric(a: &str, b: &str) -> Ordering {
+    let leading_number = |key: &str| -> Option<f64> {
+        let key = key.trim_start();
+        let length = key
+            .char_indices()
+            .take_while(|&(i, c)| c.is_ascii_digit() || c == '.' || (i == 0 && c == '-'))
+            .count();
+        key[..length].parse().ok()
+    };
+    match (leading_number(a), leading_number(b)) {
+        (Some(x), Some(y)) => x.total_cmp(&y).then_with(|| a.cmp(b)),
+        (x, y) => x.is_some().cmp(&y.is_some()).then_with(|| a.cmp(b)),
+    }
+}
+
+/// Orders runs of digits by their value and everything else as text.
+fn compare_natural(a: &str, b: &str) -> Ordering {
+    let (mut a_rest, mut b_rest) = (a, b);
+    loop {
+        let (a_run, b_run) = (leading_run(a_rest), leading_run(b_rest));
+        if a_run.is_empty() || b_run.is_empty() {
+            return a_run.len().cmp(&b_run.len()).then_with(|| a.cmp(b));
+        }
+        let is_number = |run: &str| run.starts_with(|c: char| c.is_ascii_digit());
+        let ordering = if is_number(a_run) && is_number(b_run) {
+            let (a_digits, b_digits) =
+                (a_run.trim_start_matches('0'), b_run.trim_start_matches('0'));
+            a_digits
+                .len()
+                .cmp(&b_digits.len())
+                .then_with(|| a_digits.cmp(b_digits))
+        } else {
+            a_run.cmp(b_run)
+        };
+        if ordering != Ordering::Equal {
+            return ordering;
+        }
+        a_rest = &a_rest[a_run.len()..];
+        b_rest = &b_rest[b_run.len()..];
+    }
+}
+
+/// The leading run of either digits or non-digits.
+fn leading_run(text: &str) -> &str {
+    let is_digit = text.starts_with(|c: char| c.is_ascii_digit());
+    let length = text
+        .find(|c: char| c.is_ascii_digit() != is_digit)
+        .unwrap_or(text.len());
+    &text[..length]
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        fs,
+        io::{Read, Write},
+    };
+    use tempfile::NamedTempFile;
+
+    use crate::cli::parser::{Keep, LineRange, Region, SortOrder, WriteOptions};
+    use crate::cmd::output::capture;
+    use crate::cmd::sort::{dedupe_lines, sort_lines};
+
+    const FAUX_FILE: &str = "[allow]\n\
+    host10\n\
+    Host2\n\
+    host1\n\
+    host2\n\
+    [deny]\n\
+    host3";
+
+    #[test]
+    fn test_sort_natural_between() {
+        // create test file
+        let mut file1 = NamedTempFile::new().unwrap();
+        file1.write_all(FAUX_FILE.as_bytes()).unwrap();
+        file1.flush().unwrap();
+        let file_path = file1.path().to_str().unwrap().to_owned();
+        // mutate file
+        let region = Region {
+            between: Some(vec!["^\\[allow]".to_owned(), "^\\[".to_owned()]),
+            ..Default::default()
+        };
+        let order = SortOrder {
+            natural: true,
+            ignore_case: true,
+            ..Default::default()
+        };
+        sort_lines(file_path, &region, &order, &WriteOptions::default());
+        // read in file
+        let mut mutated_contents = String::new();
+        let mut file2 = file1.reopen().unwrap();
+        file2.read_to_string(&mut mutated_contents).unwrap();
+        // compare results
+        let expected = "[allow]\n\
+        host1\n\
+        Host2\n\
+        host2\n\
+        host10\n\
+        [deny]\n\
+        host3";
+        assert_eq!(expected, mutated_contents);
+    }
+
+    #[test]
+    fn test_dedupe_keep_last() {
+        // create test file
+        let mut file1 = NamedTempFile::new().unwrap();
+        file1.write_all(FAUX_FILE.as_bytes()).unwrap();
+        file1.flush().unwrap();
+        let file_path = file1.path().to_str().unwrap().to_owned();
+        // mutate file
+        let region = Region {
+            lines: Some(LineRange { start: 2, end: -1 }),
+            ..Default::default()
+        };
+        dedupe_lines(
+            file_path,
+            &region,
+            Keep::Last,
+            true,
+            None,
+            &WriteOptions::default(),
+        );
+        // read in file
+        let mut mutated_contents = String::new();
+        let mut file2 = file1.reopen().unwrap();
+        file2.read_to_string(&mut mutated_contents).unwrap();
+        // compare results
+        let expected = "[allow]\n\
+        host10\n\
+        host1\n\
+        host2\n\
+        [deny]\n\
+        host3";
+        assert_eq!(expected, mutated_contents);
+    }
+
+    #[test]
+    fn test_sort_numeric_key_reverse() {
+        // create test file
+        let mut file1 = NamedTempFile::new().unwrap();
+        file1
+            .write_all("port=80 # web\nport=8080 # alt\nport=443 # tls".as_bytes())
+            .unwrap();
+        file1.flush().unwrap();
+        let file_path = file1.path().to_str().unwrap().to_owned();
+        // mutate file
+        let region = Region {
+            lines: Some(LineRange { start: 1, end: 3 }),
+            ..Default::default()
+        };
+        let order = SortOrder {
+            numeric: true,
+            reverse: true,
+            key: Some("port=(\\d+)".to_owned()),
+            ..Default::default()
+        };
+        sort_lines(file_path, &region, &order, &WriteOptions::default());
+        // read in file
+        let mut mutated_contents = String::new();
+        let mut file2 = file1.reopen().unwrap();
+        file2.read_to_string(&mut mutated_contents).unwrap();
+        // compare results
+        assert_eq!(
+            "port=8080 # alt\nport=443 # tls\nport=80 # web",
+            mutated_contents
+        );
+    }
+
+    #[test]
+    fn test_sort_lines_of_terminated_file() {
+        // create test file
+        let mut file1 = NamedTempFile::new().unwrap();
+        file1.write_all("b\na\n".as_bytes()).unwrap();
+        file1.flush().unwrap();
+        let file_path = file1.path().to_str().unwrap().to_owned();
+        // a range past the last line would sort the final new line in with the lines
+        let sort = |end| {
+            let region = Region {
+                lines: Some(LineRange { start: 1, end }),
+                ..Default::default()
+            };
+            let order = SortOrder::default();
+            capture(true, || {
+                sort_lines(file_path.clone(), &region, &order, &WriteOptions::default())
+            })
+        };
+        assert_eq!(exitcode::DATAERR, sort(3).code);
+        assert_eq!("b\na\n", fs::read_to_string(&file_path).unwrap());
+        // mutate file
+        assert_eq!(exitcode::OK, sort(-1).code);
+        // compare results
+        assert_eq!("a\nb\n", fs::read_to_string(&file_path).unwrap());
+    }
+}
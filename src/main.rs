@@ -1,66 +1,234 @@
 pub mod cli;
 pub mod cmd;
 
-use clap::Parser;
-use cli::parser::CLIArguments;
+use std::iter;
+
+use clap::{parser::ValueSource, ArgMatches, CommandFactory, FromArgMatches};
+use cli::parser::{CLIArguments, PlanFile, What, WriteOptions};
 use cmd::{
     add::{
-        add_after_pattern, add_before_pattern, add_via_line_number, overwrite_pattern,
-        overwrite_via_line_number,
+        add_after_pattern, add_before_pattern, add_via_line_number, append_entry,
+        overwrite_pattern, overwrite_via_line_number, prepend_entry,
     },
+    comment::{comment_lines, uncomment_lines},
+    entry::resolve_entry,
+    git, journal,
+    matcher::LineMatcher,
+    parallel::mutate_files,
+    patch::apply_patch,
+    recipe::apply_recipe,
+    relocate::{copy_lines, move_lines, swap_lines},
     remove::{
         remove_after_pattern, remove_before_pattern, remove_overwrite_pattern,
         remove_via_line_number,
     },
+    report::operation_name,
+    sort::{dedupe_lines, sort_lines},
 };
 
 use crate::cli::parser::{
-    What::{Add, Remove},
+    AddCommands::{self, Append, Prepend},
+    What::{
+        Add, Apply, Comment, Copy, Dedupe, History, Move, Plan, Recipe, Remove, Sort, Swap,
+        Uncomment, Undo,
+    },
     WhereCommands::{AfterPattern, AtLine, BeforePattern, OverwriteLine, OverwritePattern},
 };
 
 fn main() {
-    let cli_args = CLIArguments::parse();
-    let file_path = cli_args.file;
-    let maybe_entry = cli_args.entry;
+    let matches = CLIArguments::command().get_matches();
+    run(&matches, None);
+}
+
+/// Runs the command of the parsed command line, recording its changes in the plan instead of
+/// making them when it's being planned.
+fn run(matches: &ArgMatches, plan_file: Option<PlanFile>) {
+    let cli_args = CLIArguments::from_arg_matches(matches).unwrap_or_else(|error| error.exit());
     let cmd = cli_args.command;
-    let dry_run = cli_args.dry_run;
+    let mut options = cli_args.write_options;
+
+    if let Some(plan_file) = plan_file {
+        if matches!(cmd, Plan(_) | Apply(_) | Undo(_) | History) {
+            panic!("Cannot plan the Plan, Apply, Undo or History commands.")
+        }
+        if options.dry_run || options.check || options.interactive || options.emit_patch.is_some() {
+            panic!("Cannot plan a dry-run, a check, an interactive run or a patch.")
+        }
+        options.plan = Some(plan_file);
+    }
+
+    // the plan, patch and journal commands work across files
+    match (&cli_args.file, &cmd) {
+        (Some(_), Plan(_)) => {
+            panic!(
+                "Cannot provide a file before the Plan command, the command to plan goes after it."
+            )
+        }
+        (None, Plan(_)) if given_arguments(matches) => {
+            panic!(
+                "Cannot provide options before the Plan command, the command to plan takes them."
+            )
+        }
+        (None, Plan(operation)) => {
+            let Some((path, arguments)) = operation.output_and_command() else {
+                panic!("Must provide the path to write the plan to with -o/--output.")
+            };
+            let planned = CLIArguments::command()
+                .try_get_matches_from(iter::once("mute".to_owned()).chain(arguments.clone()))
+                .unwrap_or_else(|error| error.exit());
+            return run(&planned, Some(PlanFile { path, arguments }));
+        }
+        (Some(_), Apply(_) | Undo(_) | History) => {
+            panic!("Cannot provide a file while using the Apply, Undo or History commands.")
+        }
+        (None, Apply(_) | Undo(_) | History) if !cli_args.files.is_empty() => {
+            panic!("Cannot provide a file while using the Apply, Undo or History commands.")
+        }
+        (None, Apply(_)) if options.emit_patch.is_some() || options.report.is_some() => {
+            panic!("Cannot write a patch or a report while using the Apply command.")
+        }
+        (None, Apply(operation)) => {
+            return apply_patch(&operation.patch, operation.reverse, &options)
+        }
+        (None, Undo(_)) if options.emit_patch.is_some() || options.report.is_some() => {
+            panic!("Cannot write a patch or a report while using the Undo command.")
+        }
+        (None, Undo(operation)) => {
+            return journal::undo(operation.count, operation.force, &options)
+        }
+        (None, History) => return journal::history(),
+        (None, _) => panic!("Must provide a file to mutate."),
+        (Some(_), _) => {}
+    }
+    let maybe_entry = resolve_entry(cli_args.entry, &cli_args.entry_options);
 
+    // a report is written from what every file printed, so a single file gets one too
+    let parallel = !cli_args.files.is_empty() || options.report.is_some();
+    let mut files = vec![cli_args.file.unwrap()];
+    files.extend(cli_args.files);
+    if options.git_tracked_only {
+        files = git::tracked_files(files);
+    }
+    if parallel {
+        let jobs = cli_args
+            .jobs
+            .unwrap_or_else(|| std::thread::available_parallelism().map_or(1, |jobs| jobs.get()));
+        mutate_files(
+            &files,
+            jobs,
+            &operation_name(matches),
+            &options,
+            |file_path, options| mutate(file_path, maybe_entry.clone(), cmd.clone(), options),
+        );
+    }
+    if let Some(file_path) = files.pop() {
+        mutate(file_path, maybe_entry, cmd, &options);
+    }
+}
+
+/// Whether any argument was given on the command line, as opposed to left at its default.
+fn given_arguments(matches: &ArgMatches) -> bool {
+    matches
+        .ids()
+        .any(|id| matches.value_source(id.as_str()) == Some(ValueSource::CommandLine))
+}
+
+/// Mutates a single file the way the command says.
+fn mutate(file_path: String, maybe_entry: Option<String>, cmd: What, options: &WriteOptions) {
     match (maybe_entry, cmd) {
         (Some(_), Remove(_)) => {
             panic!("Cannot provide an entry to add while using the Remove command.")
         }
         (None, Add(_)) => panic!("Must provide an entry to add while using the Add command."),
+        (
+            Some(_),
+            Comment(_) | Uncomment(_) | Move(_) | Copy(_) | Swap(_) | Sort(_) | Dedupe(_),
+        ) => {
+            panic!("Cannot provide an entry while only rearranging or commenting lines.")
+        }
+        (Some(_), Recipe(_)) => {
+            panic!("Cannot provide an entry while using the Recipe command, the recipe has them.")
+        }
+        (None, Recipe(operation)) => apply_recipe(file_path, &operation.recipe, options),
+        (_, Plan(_) | Apply(_) | Undo(_) | History) => {
+            unreachable!("handled before reading the file")
+        }
+        (None, Move(operation)) => move_lines(
+            file_path,
+            &operation.source,
+            &operation.destination,
+            &operation.options,
+            options,
+        ),
+        (None, Copy(operation)) => copy_lines(
+            file_path,
+            &operation.source,
+            &operation.destination,
+            &operation.options,
+            options,
+        ),
+        (None, Sort(operation)) => {
+            sort_lines(file_path, &operation.region, &operation.order, options)
+        }
+        (None, Dedupe(operation)) => dedupe_lines(
+            file_path,
+            &operation.region,
+            operation.keep,
+            operation.ignore_case,
+            operation.key,
+            options,
+        ),
+        (None, Swap(operation)) => swap_lines(
+            file_path,
+            &operation.first,
+            &(&operation.second).into(),
+            &operation.options,
+            options,
+        ),
+        (None, Comment(operation)) => {
+            comment_lines(file_path, &operation.command, operation.marker, options)
+        }
+        (None, Uncomment(operation)) => {
+            uncomment_lines(file_path, &operation.command, operation.marker, options)
+        }
         (None, Remove(operation)) => match operation.command {
             AfterPattern(pattern_mut) => {
-                remove_after_pattern(file_path, pattern_mut.pattern, dry_run)
+                let matcher = LineMatcher::for_file(&pattern_mut, &file_path);
+                remove_after_pattern(file_path, &matcher, options)
             }
-            AtLine(line_mut) => remove_via_line_number(file_path, line_mut.line_number, dry_run),
+            AtLine(line_mut) => remove_via_line_number(file_path, line_mut.line_number, options),
             BeforePattern(pattern_mut) => {
-                remove_before_pattern(file_path, pattern_mut.pattern, dry_run)
+                let matcher = LineMatcher::for_file(&pattern_mut, &file_path);
+                remove_before_pattern(file_path, &matcher, options)
             }
             OverwritePattern(pattern_mut) => {
-                remove_overwrite_pattern(file_path, pattern_mut.pattern, dry_run)
+                let matcher = LineMatcher::for_file(&pattern_mut, &file_path);
+                remove_overwrite_pattern(file_path, &matcher, options)
             }
             OverwriteLine(line_mut) => {
-                remove_via_line_number(file_path, line_mut.line_number, dry_run)
+                remove_via_line_number(file_path, line_mut.line_number, options)
             }
         },
         (Some(new_entry), Add(operation)) => match operation.command {
-            AfterPattern(pattern_mut) => {
-                add_after_pattern(file_path, pattern_mut.pattern, new_entry, dry_run)
+            Append => append_entry(file_path, new_entry, options),
+            Prepend => prepend_entry(file_path, new_entry, options),
+            AddCommands::Where(AfterPattern(pattern_mut)) => {
+                let matcher = LineMatcher::for_file(&pattern_mut, &file_path);
+                add_after_pattern(file_path, &matcher, new_entry, options)
             }
-            AtLine(line_mut) => {
-                add_via_line_number(file_path, line_mut.line_number, new_entry, dry_run)
+            AddCommands::Where(AtLine(line_mut)) => {
+                add_via_line_number(file_path, line_mut.line_number, new_entry, options)
             }
-            BeforePattern(pattern_mut) => {
-                add_before_pattern(file_path, pattern_mut.pattern, new_entry, dry_run)
+            AddCommands::Where(BeforePattern(pattern_mut)) => {
+                let matcher = LineMatcher::for_file(&pattern_mut, &file_path);
+                add_before_pattern(file_path, &matcher, new_entry, options)
             }
-            OverwritePattern(pattern_mut) => {
-                overwrite_pattern(file_path, pattern_mut.pattern, new_entry, dry_run)
+            AddCommands::Where(OverwritePattern(pattern_mut)) => {
+                let matcher = LineMatcher::for_file(&pattern_mut, &file_path);
+                overwrite_pattern(file_path, &matcher, new_entry, options)
             }
-            OverwriteLine(line_mut) => {
-                overwrite_via_line_number(file_path, line_mut.line_number, new_entry, dry_run)
+            AddCommands::Where(OverwriteLine(line_mut)) => {
+                overwrite_via_line_number(file_path, line_mut.line_number, new_entry, options)
             }
         },
     }
@@ -0,0 +1,99 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    process::Command,
+    sync::Once,
+};
+
+use tempfile::tempdir;
+
+/// The mute binary built with the release profile, which is how it's shipped. A file of a run
+/// over several files, or with `--report`, ends by unwinding, which a profile that aborts on panic
+/// would turn into a crash.
+fn release_binary() -> PathBuf {
+    static BUILD: Once = Once::new();
+    BUILD.call_once(|| {
+        let status = Command::new(env!("CARGO"))
+            .args(["build", "--release", "--quiet", "--manifest-path"])
+            .arg(Path::new(env!("CARGO_MANIFEST_DIR")).join("Cargo.toml"))
+            .status()
+            .unwrap();
+        assert!(status.success());
+    });
+    let binary = Path::new(env!("CARGO_BIN_EXE_mute"));
+    let target = binary.parent().unwrap().parent().unwrap();
+    target.join("release").join(binary.file_name().unwrap())
+}
+
+/// Runs the release binary in `dir` with the arguments, returning its exit code and what it
+/// printed.
+fn mute(dir: &Path, args: &[&str]) -> (Option<i32>, String) {
+    let output = Command::new(release_binary())
+        .current_dir(dir)
+        .args(args)
+        .output()
+        .unwrap();
+    (
+        output.status.code(),
+        String::from_utf8(output.stdout).unwrap(),
+    )
+}
+
+#[test]
+fn test_failing_file_of_many() {
+    // create test files
+    let dir = tempdir().unwrap();
+    fs::write(dir.path().join("x.txt"), "a\n").unwrap();
+    fs::write(dir.path().join("y.txt"), "b\n").unwrap();
+    // mutate files, one of them failing
+    let args = ["--no-journal", "x.txt", "--file", "y.txt"];
+    let (code, stdout) = mute(
+        dir.path(),
+        &[&args[..], &["NEW", "add", "after-pattern", "^a"]].concat(),
+    );
+    // compare results
+    assert_eq!(Some(exitcode::DATAERR), code);
+    assert!(stdout.contains("(File: y.txt)\nWARNING:"));
+    assert_eq!(
+        "a\nNEW\n",
+        fs::read_to_string(dir.path().join("x.txt")).unwrap()
+    );
+    assert_eq!("b\n", fs::read_to_string(dir.path().join("y.txt")).unwrap());
+}
+
+#[test]
+fn test_failing_report() {
+    // create test file
+    let dir = tempdir().unwrap();
+    fs::write(dir.path().join("x.txt"), "a\n").unwrap();
+    let mutation = ["x.txt", "NEW", "add", "after-pattern", "^zz"];
+    // mutate file, reporting the failure as TAP
+    let (code, stdout) = mute(
+        dir.path(),
+        &[&["--no-journal", "--report", "tap"][..], &mutation].concat(),
+    );
+    // compare results
+    assert_eq!(Some(exitcode::DATAERR), code);
+    assert!(
+        stdout.starts_with("TAP version 13\n1..1\nnot ok 1 - x.txt: NEW add after-pattern ^zz\n")
+    );
+    assert!(stdout.contains("  exit_code: 65\n"));
+
+    // mutate file, reporting the failure as JUnit
+    let (code, _) = mute(
+        dir.path(),
+        &[
+            &["--no-journal", "--report", "junit=report.xml"][..],
+            &mutation,
+        ]
+        .concat(),
+    );
+    // read in file
+    let report = fs::read_to_string(dir.path().join("report.xml")).unwrap();
+    // compare results
+    assert_eq!(Some(exitcode::DATAERR), code);
+    assert!(report.contains("<testsuites tests=\"1\" failures=\"1\">"));
+    assert!(report.contains("<testcase classname=\"NEW add after-pattern ^zz\" name=\"x.txt\">"));
+    assert!(report.contains("type=\"exit code 65\""));
+    assert_eq!("a\n", fs::read_to_string(dir.path().join("x.txt")).unwrap());
+}